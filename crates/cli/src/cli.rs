@@ -1,8 +1,11 @@
 use std::path::PathBuf;
 
 use clap::{command, Subcommand};
+use leaky_common::prelude::{parse_leaky_cid, Chunker, Cid, DEFAULT_ADD_CONCURRENCY};
 use url::Url;
 
+use crate::ops::{RemotePath, DEFAULT_VERIFY_CONCURRENCY};
+
 pub use clap::Parser;
 
 #[derive(Parser, Debug)]
@@ -19,19 +22,151 @@ pub enum Command {
         maybe_ipfs_rpc_url: Option<Url>,
         #[clap(long = "leaky-api", short = 'l')]
         maybe_leaky_api_url: Option<Url>,
+        /// How to chunk file data: size-<bytes> or rabin
+        #[clap(long, default_value_t = Chunker::default())]
+        chunker: Chunker,
+        /// Wrap data leaves in dag-pb instead of storing them as raw blocks
+        #[clap(long)]
+        no_raw_leaves: bool,
+        /// Compress file data with zstd before adding it, where that saves space
+        #[clap(long)]
+        compress: bool,
+    },
+    Add {
+        /// Tag added files with metadata extracted from their content
+        #[clap(long)]
+        auto_tag: bool,
+        /// Record each file's original name in its metadata
+        #[clap(long)]
+        record_filename: bool,
+        /// Add whatever symlinks point to instead of skipping them
+        #[clap(long)]
+        follow_symlinks: bool,
+        /// Refuse to add files larger than this many bytes
+        #[clap(long)]
+        max_file_size: Option<u64>,
+        /// Skip files over --max-file-size instead of aborting
+        #[clap(long, requires = "max_file_size")]
+        skip_large: bool,
+        /// How many files to hash or upload at once
+        #[clap(long, default_value_t = DEFAULT_ADD_CONCURRENCY)]
+        concurrency: usize,
     },
-    Add,
     Tag {
         #[clap(long, short)]
         path: PathBuf,
+        /// Metadata as a JSON object, or - to read it from stdin
+        #[clap(long, short, required_unless_present = "metadata_file")]
+        metadata: Option<String>,
+        /// Read the metadata from a JSON file instead
+        #[clap(long, conflicts_with = "metadata")]
+        metadata_file: Option<PathBuf>,
+        /// Merge the metadata into the existing metadata instead of replacing it
+        #[clap(long)]
+        merge: bool,
+    },
+    /// Bump a file's updated_at without changing its data or metadata
+    Touch {
+        path: PathBuf,
+    },
+    Rm {
         #[clap(long, short)]
-        metadata: String,
+        path: PathBuf,
+        /// Required to remove a directory and everything beneath it
+        #[clap(long, short)]
+        recursive: bool,
+        /// Skip the confirmation prompt when removing a directory
+        #[clap(long, short)]
+        yes: bool,
+    },
+    /// Print the resolved local state and config
+    Info,
+    /// List the versions leading up to the local state, newest first
+    History {
+        /// Only list this many versions
+        #[clap(long)]
+        limit: Option<usize>,
+        /// Print the versions as json
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show what's inside a node or manifest block
+    Inspect {
+        #[clap(value_parser = parse_leaky_cid)]
+        cid: Cid,
+        /// Print the block as dag-json
+        #[clap(long)]
+        json: bool,
+    },
+    Stat {
+        /// Also report what's held in the local block cache
+        #[clap(long)]
+        debug: bool,
     },
-    Stat,
     Push,
-    Pull,
+    Pull {
+        /// Only pull this file or directory, leaving the rest of the working tree alone
+        #[clap(conflicts_with_all = ["cid", "since"])]
+        path: Option<PathBuf>,
+        /// Pull a specific root instead of the latest, without updating the local state
+        #[clap(long, requires = "into", value_parser = parse_leaky_cid)]
+        cid: Option<Cid>,
+        /// Directory to write the pulled root into
+        #[clap(long, requires = "cid")]
+        into: Option<PathBuf>,
+        /// Only write and remove the files that changed since this root
+        #[clap(long, conflicts_with = "cid", value_parser = parse_leaky_cid)]
+        since: Option<Cid>,
+    },
+    /// Import a UnixFS directory already on the IPFS node into the bucket
+    ImportIpfs {
+        /// Cid of the UnixFS directory
+        cid: Cid,
+        /// Where in the bucket to put the imported directory
+        #[clap(long, short, default_value = "/")]
+        path: PathBuf,
+    },
+    /// Copy a file or directory out of another root, without re-uploading it
+    CpRemote {
+        /// Source, given as <cid>:<path>
+        source: RemotePath,
+        /// Where in the bucket to put the copy
+        dest: PathBuf,
+    },
+    /// Check the remote is pinning every block in the current root
+    VerifyRemote {
+        /// How many blocks to check at once
+        #[clap(long, default_value_t = DEFAULT_VERIFY_CONCURRENCY)]
+        concurrency: usize,
+    },
+    /// Print the data of a file in the bucket
+    Cat {
+        path: PathBuf,
+        /// Print a directory's listing as json, rather than refusing
+        #[clap(long)]
+        listing: bool,
+    },
+    /// List a directory in the bucket
     Ls {
-        #[clap(long, short)]
+        #[clap(long, short, default_value = "/")]
         path: PathBuf,
+        /// List a past root instead of the local state
+        #[clap(long, value_parser = parse_leaky_cid)]
+        cid: Option<Cid>,
+        /// List everything beneath the directory
+        #[clap(long, short)]
+        recursive: bool,
+        /// Print the listing as json
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show the bucket as an indented tree of directories and files, with their cids
+    Tree {
+        /// Show a past root instead of the local state
+        #[clap(long, value_parser = parse_leaky_cid)]
+        cid: Option<Cid>,
+        /// Also summarize each file's object
+        #[clap(long)]
+        objects: bool,
     },
 }
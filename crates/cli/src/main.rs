@@ -1,14 +1,17 @@
-use std::fmt::Display;
-
+use leaky_common::prelude::AddOptions;
 use url::Url;
 
 mod cli;
 mod ops;
 
 use cli::{Cli, Command, Parser};
+use ops::utils::pretty_print;
 use ops::{
-    add, init, pull, push, stat, tag, AddError, InitError, PullError, PushError, StatError,
-    TagError,
+    add, cache_stats, cat, cp_remote, history, import_ipfs, info, init, inspect, ls, pull,
+    pull_into, pull_path, pull_since, push, rm, stat, tag, touch, tree, verify_remote, AddError,
+    Cat, CatError, CpRemoteError, DiffOptions, HistoryError, ImportError, InfoError, InitError,
+    InspectError, LsError, MetadataSource, PullError, PushError, RmError, StatError, TagError,
+    TouchError, TreeError, VerifyError,
 };
 
 #[tokio::main]
@@ -23,6 +26,9 @@ pub async fn run() -> Result<(), AppError> {
         Command::Init {
             maybe_ipfs_rpc_url,
             maybe_leaky_api_url,
+            chunker,
+            no_raw_leaves,
+            compress,
         } => {
             let ipfs_rpc = match maybe_ipfs_rpc_url {
                 Some(url) => url,
@@ -32,50 +38,147 @@ pub async fn run() -> Result<(), AppError> {
                 Some(url) => url,
                 None => Url::parse("http://localhost:3000").unwrap(),
             };
-            let cid = init(ipfs_rpc, leaky_api).await?;
+            let add_options = AddOptions {
+                chunker,
+                raw_leaves: !no_raw_leaves,
+            };
+            let cid = init(ipfs_rpc, leaky_api, add_options, compress).await?;
+            pretty_print(format!("LeakyBucket @ {}", cid));
+        }
+        Command::Add {
+            auto_tag,
+            record_filename,
+            follow_symlinks,
+            max_file_size,
+            skip_large,
+            concurrency,
+        } => {
+            let options = DiffOptions {
+                follow_symlinks,
+                max_file_size,
+                skip_large,
+            };
+            let cid = add(auto_tag, record_filename, options, concurrency).await?;
             pretty_print(format!("LeakyBucket @ {}", cid));
         }
-        Command::Add => {
-            let cid = add().await?;
+        Command::Tag {
+            path,
+            metadata,
+            metadata_file,
+            merge,
+        } => {
+            let source = match (metadata, metadata_file) {
+                (_, Some(path)) => MetadataSource::File(path),
+                (Some(value), None) => MetadataSource::Inline(value),
+                // clap requires one or the other
+                (None, None) => unreachable!(),
+            };
+            let cid = tag(path, source, merge).await?;
             pretty_print(format!("LeakyBucket @ {}", cid));
         }
-        Command::Tag { path, metadata } => {
-            let cid = tag(path, metadata).await?;
+        Command::Touch { path } => {
+            let cid = touch(path).await?;
             pretty_print(format!("LeakyBucket @ {}", cid));
         }
-        Command::Stat => {
+        Command::Rm {
+            path,
+            recursive,
+            yes,
+        } => {
+            let cid = rm(path, recursive, yes).await?;
+            pretty_print(format!("LeakyBucket @ {}", cid));
+        }
+        Command::Info => {
+            let info = info().await?;
+            println!("{}", info);
+        }
+        Command::History { limit, json } => {
+            let entries = history(limit).await?;
+            if json {
+                let json = serde_json::to_string_pretty(&entries).map_err(anyhow::Error::from)?;
+                println!("{}", json);
+            } else {
+                for entry in entries {
+                    println!("{}", entry);
+                }
+            }
+        }
+        Command::Inspect { cid, json } => {
+            let inspected = inspect(cid, json).await?;
+            println!("{}", inspected);
+        }
+        Command::Stat { debug } => {
             let stats = stat().await?;
             println!("{}", stats);
+            if debug {
+                let cache_stats = cache_stats().await?;
+                pretty_print(format!("nodes: {}", cache_stats.node_count));
+                pretty_print(format!("data links: {}", cache_stats.data_link_count));
+                pretty_print(format!("cache bytes: {}", cache_stats.cache_bytes));
+            }
         }
         Command::Push => {
             let cid = push().await?;
             pretty_print(format!("LeakyBucket @ {}", cid));
         }
-        Command::Pull => {
-            let cid = pull().await?;
+        Command::Pull {
+            path,
+            cid,
+            into,
+            since,
+        } => {
+            let cid = match (path, cid, into, since) {
+                (Some(path), _, _, _) => pull_path(path).await?,
+                (_, Some(cid), Some(into), _) => pull_into(cid, into).await?,
+                (_, _, _, Some(since)) => pull_since(since).await?,
+                _ => pull().await?,
+            };
             pretty_print(format!("LeakyBucket @ {}", cid));
         }
-
-        /*
-                Command::Add { root, path } => {
-                    leaky.pull(&root).await?;
-                    // Read the data as a stream
-                    let data = std::fs::read(&path)?;
-                    let data = std::io::Cursor::new(data);
-
-                    let cid = leaky.add(&path, data, None).await?;
-                    pretty_print(&format!("{} -> {}", &path.to_string_lossy(), &cid));
-                    changed = true;
-                }
-                Command::Ls { root, path } => {
-                    leaky.pull(&root).await?;
-                    let entries = leaky.ls(path).await?;
-                    for entry in entries {
-                        pretty_print(&format!("{} -> {}", entry.0, entry.0));
-                    }
+        Command::ImportIpfs { cid, path } => {
+            let cid = import_ipfs(cid, path).await?;
+            pretty_print(format!("LeakyBucket @ {}", cid));
+        }
+        Command::CpRemote { source, dest } => {
+            let cid = cp_remote(source, dest).await?;
+            pretty_print(format!("LeakyBucket @ {}", cid));
+        }
+        Command::VerifyRemote { concurrency } => {
+            let cid = verify_remote(concurrency).await?;
+            pretty_print(format!("LeakyBucket @ {} is fully pinned", cid));
+        }
+        Command::Cat { path, listing } => match cat(path, listing).await? {
+            Cat::Data(data) => {
+                use std::io::Write;
+                std::io::stdout()
+                    .write_all(&data)
+                    .map_err(anyhow::Error::from)?;
+            }
+            Cat::Listing(entries) => {
+                let json = serde_json::to_string_pretty(&entries).map_err(anyhow::Error::from)?;
+                println!("{}", json);
+            }
+        },
+        Command::Ls {
+            path,
+            cid,
+            recursive,
+            json,
+        } => {
+            let entries = ls(path, cid, recursive).await?;
+            if json {
+                let json = serde_json::to_string_pretty(&entries).map_err(anyhow::Error::from)?;
+                println!("{}", json);
+            } else {
+                for entry in entries {
+                    println!("{}", entry);
                 }
-        */
-        _ => {}
+            }
+        }
+        Command::Tree { cid, objects } => {
+            let tree = tree(cid, objects).await?;
+            println!("{}", tree);
+        }
     };
     Ok(())
 }
@@ -90,12 +193,34 @@ pub enum AppError {
     Add(#[from] AddError),
     #[error("Stat error: {0}")]
     Stat(#[from] StatError),
+    #[error("Info error: {0}")]
+    Info(#[from] InfoError),
+    #[error("Cat error: {0}")]
+    Cat(#[from] CatError),
+    #[error("Ls error: {0}")]
+    Ls(#[from] LsError),
+    #[error("Tree error: {0}")]
+    Tree(#[from] TreeError),
+    #[error("History error: {0}")]
+    History(#[from] HistoryError),
+    #[error("Inspect error: {0}")]
+    Inspect(#[from] InspectError),
     #[error("Push error: {0}")]
     Push(#[from] PushError),
     #[error("Pull error: {0}")]
     Pull(#[from] PullError),
     #[error("Tag error: {0}")]
     Tag(#[from] TagError),
+    #[error("Touch error: {0}")]
+    Touch(#[from] TouchError),
+    #[error("Rm error: {0}")]
+    Rm(#[from] RmError),
+    #[error("Verify error: {0}")]
+    Verify(#[from] VerifyError),
+    #[error("Import error: {0}")]
+    Import(#[from] ImportError),
+    #[error("Copy error: {0}")]
+    CpRemote(#[from] CpRemoteError),
 }
 
 fn capture_error<T>(result: Result<T, AppError>) {
@@ -106,8 +231,3 @@ fn capture_error<T>(result: Result<T, AppError>) {
         }
     }
 }
-
-fn pretty_print<T: Display>(value: T) {
-    let bullet = "•";
-    println!("{} {}", bullet, value);
-}
@@ -1,43 +1,49 @@
-use std::fs::File;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use leaky_common::prelude::*;
 
-use super::diff::{diff, DiffError};
+use super::diff::{diff, DiffError, DiffOptions};
 
 use super::change_log::ChangeType;
 use super::utils;
 
+/// Metadata key the original file name is recorded under
+pub const FILENAME_KEY: &str = "filename";
+
+// How many files to hold open at once while adding
+const ADD_BATCH_SIZE: usize = 256;
+
 fn abs_path(path: &PathBuf) -> Result<PathBuf, DiffError> {
     let path = PathBuf::from("/").join(path);
     Ok(path)
 }
 
-pub async fn add() -> Result<Cid, AddError> {
+pub async fn add(
+    auto_tag: bool,
+    record_filename: bool,
+    options: DiffOptions,
+    concurrency: usize,
+) -> Result<Cid, AddError> {
+    let _lock = utils::lock_on_disk()?;
     let (mut leaky, mut change_log) = utils::load_on_disk().await?;
+    leaky.set_add_concurrency(concurrency);
 
     // Diff against the cwd
-    let updates = diff(&leaky, &mut change_log).await?;
+    let updates = diff(&leaky, &mut change_log, &options).await?;
 
     let root_cid = leaky.cid()?;
 
-    let change_log_iter = updates.iter().map(|(path, (hash, change))| {
-        let abs_path = abs_path(path).unwrap();
-        (path.clone(), abs_path, (hash, change))
-    });
+    let mut to_add = vec![];
     // Iterate over the ChangeLog -- play updates against the base ... probably better to do this
-    for (path, abs_path, (_hash, diff_type)) in change_log_iter {
+    for (path, (_hash, diff_type)) in updates.iter() {
+        let abs_path = abs_path(path)?;
         match diff_type {
-            ChangeType::Added { modified: true } => {
-                let file = File::open(&path)?;
-                leaky.add(&abs_path, file, None, true).await?;
-            }
-
-            ChangeType::Modified => {
-                let file = File::open(&path)?;
-                leaky.add(&abs_path, file, None, true).await?;
+            ChangeType::Added { modified: true } | ChangeType::Modified => {
+                to_add.push((path.clone(), abs_path));
             }
 
+            // Removals go first, so a file replaced by a directory is out of the way
             ChangeType::Removed => {
                 leaky.rm(&abs_path).await?;
             }
@@ -49,6 +55,50 @@ pub async fn add() -> Result<Cid, AddError> {
         }
     }
 
+    // Hash the data in batches, so we don't hold every file open at once
+    for batch in to_add.chunks(ADD_BATCH_SIZE) {
+        let items = batch
+            .iter()
+            .map(|(path, abs_path)| Ok((abs_path.clone(), utils::open_file(path)?, true)))
+            .collect::<Result<Vec<_>, AddError>>()?;
+        leaky.add_many(items).await?;
+    }
+
+    if auto_tag || record_filename {
+        for (path, abs_path) in to_add.iter() {
+            let mut metadata = BTreeMap::new();
+            if auto_tag {
+                if let Some(tags) = super::auto_tag::auto_tag(path)? {
+                    metadata.extend(tags);
+                }
+            }
+            if record_filename {
+                if let Some(name) = path.file_name() {
+                    let name = name.to_string_lossy().to_string();
+                    metadata.insert(FILENAME_KEY.to_string(), Ipld::String(name));
+                }
+            }
+            // Merge so we don't clobber anything tagged by hand
+            if !metadata.is_empty() {
+                leaky.tag(abs_path, &metadata, true).await?;
+            }
+        }
+    }
+
+    // Directories only exist implicitly through their children,
+    //  so explicitly mirror any empty directories in the working tree.
+    //  Drop the ones that were deleted first, since removing one can empty out its parent
+    for dir in leaky.empty_dirs().await? {
+        if !dir.strip_prefix("/").unwrap().is_dir() {
+            leaky.rm(&dir).await?;
+        }
+    }
+    for (tree, path) in utils::fs_tree(options.follow_symlinks)?.iter() {
+        if tree.is_dir() && utils::is_empty_dir(&path)? {
+            leaky.mkdir(&abs_path(&path)?).await?;
+        }
+    }
+
     let new_root_cid = leaky.cid()?;
 
     if new_root_cid == root_cid {
@@ -82,3 +132,72 @@ pub enum AddError {
     #[error("device error: {0}")]
     Leaky(#[from] LeakyError),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{tag, MetadataSource};
+
+    async fn metadata(path: &str) -> BTreeMap<String, Ipld> {
+        let (leaky, _) = utils::load_on_disk().await.unwrap();
+        leaky
+            .get_object(&PathBuf::from(path))
+            .await
+            .unwrap()
+            .unwrap()
+            .metadata()
+            .clone()
+    }
+
+    fn filename(name: &str) -> Option<Ipld> {
+        Some(Ipld::String(name.to_string()))
+    }
+
+    #[tokio::test]
+    async fn add_records_filename() {
+        let tree = TestTree::init("record-filename").await;
+        tree.write("foo", "foo");
+        tree.write("dir/foo.tar.gz", "archive");
+        add(false, true, DiffOptions::default(), DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+        assert_eq!(
+            metadata("/foo").await.get(FILENAME_KEY).cloned(),
+            filename("foo")
+        );
+        assert_eq!(
+            metadata("/dir/foo.tar.gz").await.get(FILENAME_KEY).cloned(),
+            filename("foo.tar.gz")
+        );
+
+        // Re-adding merges into what's there, so hand made tags and unchanged files keep theirs
+        let source = MetadataSource::Inline(r#"{"k": "v"}"#.to_string());
+        tag(PathBuf::from("dir/foo.tar.gz"), source, true)
+            .await
+            .unwrap();
+        tree.write("dir/foo.tar.gz", "new archive");
+        add(false, true, DiffOptions::default(), DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+        let archive = metadata("/dir/foo.tar.gz").await;
+        assert_eq!(archive.get(FILENAME_KEY).cloned(), filename("foo.tar.gz"));
+        assert_eq!(archive.get("k"), Some(&Ipld::String("v".to_string())));
+        assert_eq!(
+            metadata("/foo").await.get(FILENAME_KEY).cloned(),
+            filename("foo")
+        );
+
+        // Nothing is recorded unless asked for
+        tree.write("bar", "bar");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        assert_eq!(metadata("/bar").await.get(FILENAME_KEY), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use leaky_common::prelude::*;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const GIF_SIGNATURE: &[u8] = b"GIF8";
+
+/// Extract basic metadata from common content types, keyed by file extension.
+///  Returns None if the file isn't a type we know how to inspect
+pub fn auto_tag(path: &Path) -> std::io::Result<Option<BTreeMap<String, Ipld>>> {
+    let extension = match path.extension() {
+        Some(ext) => ext.to_string_lossy().to_lowercase(),
+        None => return Ok(None),
+    };
+    match extension.as_str() {
+        "png" | "gif" => image_metadata(path),
+        "md" | "markdown" => markdown_metadata(path),
+        _ => Ok(None),
+    }
+}
+
+// Read the dimensions straight out of the image header
+fn image_metadata(path: &Path) -> std::io::Result<Option<BTreeMap<String, Ipld>>> {
+    let mut header = Vec::with_capacity(24);
+    let file = std::fs::File::open(path)?;
+    file.take(24).read_to_end(&mut header)?;
+
+    let dimensions =
+        if header.len() == 24 && header.starts_with(PNG_SIGNATURE) && &header[12..16] == b"IHDR" {
+            let width = u32::from_be_bytes(header[16..20].try_into().unwrap());
+            let height = u32::from_be_bytes(header[20..24].try_into().unwrap());
+            Some((width, height))
+        } else if header.len() >= 10 && header.starts_with(GIF_SIGNATURE) {
+            let width = u16::from_le_bytes(header[6..8].try_into().unwrap());
+            let height = u16::from_le_bytes(header[8..10].try_into().unwrap());
+            Some((width as u32, height as u32))
+        } else {
+            None
+        };
+
+    Ok(dimensions.map(|(width, height)| {
+        let mut metadata = BTreeMap::new();
+        metadata.insert("width".to_string(), Ipld::Integer(width as i128));
+        metadata.insert("height".to_string(), Ipld::Integer(height as i128));
+        metadata
+    }))
+}
+
+// Use the first top level heading as the title. Files that aren't valid UTF-8 just
+//  don't get one
+fn markdown_metadata(path: &Path) -> std::io::Result<Option<BTreeMap<String, Ipld>>> {
+    let file = std::fs::File::open(path)?;
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if let Some(title) = line.strip_prefix("# ") {
+            let mut metadata = BTreeMap::new();
+            metadata.insert("title".to_string(), Ipld::String(title.trim().to_string()));
+            return Ok(Some(metadata));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::ops::utils::{self, test::TestTree};
+    use crate::ops::{add, DiffOptions};
+
+    // Just enough of a PNG for the header to parse
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0]);
+        data
+    }
+
+    #[tokio::test]
+    async fn add_with_auto_tag() {
+        let _tree = TestTree::init("auto-tag").await;
+        std::fs::write("image.png", png(640, 480)).unwrap();
+        std::fs::write("notes.md", "some preamble\n# Notes\nbody\n").unwrap();
+        std::fs::write("binary.md", b"# \xff\xfe not utf-8\n").unwrap();
+        add(true, false, DiffOptions::default(), DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+
+        let (leaky, _) = utils::load_on_disk().await.unwrap();
+        let metadata = |path: &str| {
+            let leaky = &leaky;
+            let path = PathBuf::from(path);
+            async move {
+                leaky
+                    .get_object(&path)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .metadata()
+                    .clone()
+            }
+        };
+
+        let image = metadata("/image.png").await;
+        assert_eq!(image.get("width"), Some(&Ipld::Integer(640)));
+        assert_eq!(image.get("height"), Some(&Ipld::Integer(480)));
+        let notes = metadata("/notes.md").await;
+        assert_eq!(notes.get("title"), Some(&Ipld::String("Notes".to_string())));
+        // Added all the same, just without a title
+        let binary = metadata("/binary.md").await;
+        assert_eq!(binary.get("title"), None);
+    }
+}
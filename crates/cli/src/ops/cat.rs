@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+
+use super::ls::{ls, LsEntry, LsError};
+use super::utils;
+
+/// What's at a path: a file's data, or a directory's listing
+pub enum Cat {
+    Data(Vec<u8>),
+    Listing(Vec<LsEntry>),
+}
+
+/// Read the file at a path. With `listing`, a directory gives its listing
+///  instead of an error
+pub async fn cat(path: PathBuf, listing: bool) -> Result<Cat, CatError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let path = PathBuf::from("/").join(path);
+    match leaky.cat(&path).await {
+        Ok(data) => Ok(Cat::Data(data)),
+        Err(LeakyError::PathNotFile(_)) if listing => {
+            Ok(Cat::Listing(ls(path, None, false).await?))
+        }
+        Err(LeakyError::PathNotFile(_)) => Err(CatError::PathIsDirectory(path)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CatError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("ls error: {0}")]
+    Ls(#[from] LsError),
+    #[error("{0} is a directory, use --listing to list it")]
+    PathIsDirectory(PathBuf),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, push, DiffOptions};
+
+    #[tokio::test]
+    async fn cat_files_and_dirs() {
+        let tree = TestTree::init("cat").await;
+        tree.write("dir/a", "a");
+        tree.write("dir/b", "b");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        push().await.unwrap();
+
+        match cat(PathBuf::from("dir/a"), false).await.unwrap() {
+            Cat::Data(data) => assert_eq!(data, b"a"),
+            Cat::Listing(_) => panic!("expected data"),
+        }
+        // Files are read the same with or without `listing`
+        match cat(PathBuf::from("/dir/b"), true).await.unwrap() {
+            Cat::Data(data) => assert_eq!(data, b"b"),
+            Cat::Listing(_) => panic!("expected data"),
+        }
+
+        match cat(PathBuf::from("dir"), true).await.unwrap() {
+            Cat::Listing(entries) => {
+                let paths = entries
+                    .iter()
+                    .map(|entry| entry.path.clone())
+                    .collect::<Vec<_>>();
+                assert_eq!(paths, ["/dir/a", "/dir/b"].map(PathBuf::from).to_vec());
+            }
+            Cat::Data(_) => panic!("expected a listing"),
+        }
+        assert!(matches!(
+            cat(PathBuf::from("dir"), false).await,
+            Err(CatError::PathIsDirectory(path)) if path == PathBuf::from("/dir")
+        ));
+        assert!(matches!(
+            cat(PathBuf::from("missing"), false).await,
+            Err(CatError::Leaky(_))
+        ));
+    }
+}
@@ -28,6 +28,10 @@ pub enum ChangeType {
     Modified,
     // Covers files that have been removed since the last call to `push`
     Removed,
+    // Covers new files linked to data that's already on the node, like those
+    //  brought in by `import`. Their links go out with the next `push`, but
+    //  there's nothing to upload
+    Linked,
 }
 
 impl std::fmt::Display for ChangeType {
@@ -37,12 +41,14 @@ impl std::fmt::Display for ChangeType {
             Self::Added { .. } => "\x1b[0;32mAdded\x1b[0m",
             Self::Modified => "\x1b[0;33mModified\x1b[0m",
             Self::Removed => "\x1b[0;31mRemoved\x1b[0m",
+            Self::Linked => "\x1b[0;32mLinked\x1b[0m",
         };
         write!(f, "{}", s)
     }
 }
 
-/// Tracks what files are in the local clone and their hashes
+/// Tracks what files are in the local clone and their hashes.
+///  Backed by a BTreeMap so entries are always in path order
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ChangeLog(BTreeMap<PathBuf, (Cid, ChangeType)>);
 
@@ -64,6 +70,18 @@ impl ChangeLog {
     pub fn new() -> Self {
         Self(BTreeMap::new())
     }
+
+    /// Serialize for writing to disk. Entries are sorted by path and pretty printed,
+    ///  so re-saving an unchanged log produces the same bytes
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let mut json = serde_json::to_string_pretty(&self)?;
+        json.push('\n');
+        Ok(json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
 }
 
 pub struct DisplayableChangeLog(pub ChangeLog);
@@ -80,3 +98,61 @@ impl std::fmt::Display for DisplayableChangeLog {
         write!(f, "{}", s)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, utils, DiffOptions};
+
+    #[test]
+    fn json_is_stable() {
+        let entries = [
+            ("b", ChangeType::Modified),
+            ("a/c", ChangeType::Added { modified: true }),
+            ("a", ChangeType::Removed),
+            ("d", ChangeType::Base),
+            ("e", ChangeType::Linked),
+        ];
+        let mut forward = ChangeLog::new();
+        for (path, change) in entries.iter().cloned() {
+            forward.insert(PathBuf::from(path), (Cid::default(), change));
+        }
+        let mut backward = ChangeLog::new();
+        for (path, change) in entries.iter().rev().cloned() {
+            backward.insert(PathBuf::from(path), (Cid::default(), change));
+        }
+
+        // The order entries went in doesn't matter
+        let json = forward.to_json().unwrap();
+        assert_eq!(json, backward.to_json().unwrap());
+        assert!(json.ends_with('\n'));
+
+        // And a round trip gives back the same bytes
+        let parsed = ChangeLog::from_json(&json).unwrap();
+        assert_eq!(parsed, forward);
+        assert_eq!(parsed.to_json().unwrap(), json);
+    }
+
+    #[tokio::test]
+    async fn resave_is_byte_identical() {
+        let tree = TestTree::init("change-log-resave").await;
+        for path in ["z", "a", "m/n", "m/a"] {
+            tree.write(path, path);
+        }
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+
+        let log_path = PathBuf::from(utils::DEFAULT_LOCAL_DIR).join(utils::DEFAULT_CHAGE_LOG_NAME);
+        let before = std::fs::read(&log_path).unwrap();
+        let (mut leaky, change_log) = utils::load_on_disk().await.unwrap();
+        utils::save_on_disk(&mut leaky, &change_log).await.unwrap();
+        assert_eq!(std::fs::read(&log_path).unwrap(), before);
+    }
+}
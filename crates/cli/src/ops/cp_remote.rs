@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use leaky_common::prelude::*;
+
+use super::change_log::ChangeType;
+use super::pull::{pull_file, PullError};
+use super::push::{push, PushError};
+use super::utils;
+use super::verify::DEFAULT_VERIFY_CONCURRENCY;
+
+/// A path within a specific remote root, written as `<cid>:<path>`
+#[derive(Debug, Clone)]
+pub struct RemotePath {
+    pub cid: Cid,
+    pub path: PathBuf,
+}
+
+impl FromStr for RemotePath {
+    type Err = CpRemoteError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (cid, path) = s
+            .split_once(':')
+            .ok_or_else(|| CpRemoteError::InvalidRemotePath(s.to_string()))?;
+        let cid =
+            parse_leaky_cid(cid).map_err(|_| CpRemoteError::InvalidRemotePath(s.to_string()))?;
+        let path = utils::bucket_path(Path::new(path))
+            .map_err(|_| CpRemoteError::InvalidRemotePath(s.to_string()))?;
+        Ok(Self { cid, path })
+    }
+}
+
+/// Copy a file or directory from another root into the bucket, then push
+pub async fn cp_remote(source: RemotePath, dest: PathBuf) -> Result<Cid, CpRemoteError> {
+    {
+        let _lock = utils::lock_on_disk()?;
+        let (mut leaky, mut change_log) = utils::load_on_disk().await?;
+
+        let local_dest = utils::local_path(&dest)?;
+        let dest = utils::bucket_path(&dest)?;
+        if local_dest.as_os_str().is_empty() || local_dest.exists() {
+            return Err(CpRemoteError::PathExists(local_dest));
+        }
+
+        let (remote, _) = utils::pull_remote(Some(source.cid)).await?;
+        // We're only linking to the source's blocks, so they had better be there
+        let missing = remote.missing_blocks(DEFAULT_VERIFY_CONCURRENCY).await?;
+        if !missing.is_empty() {
+            return Err(CpRemoteError::MissingBlocks(missing.len()));
+        }
+
+        leaky.copy_from(&remote, &source.path, &dest).await?;
+
+        // Mirror the copy into the working tree so the next diff agrees with the bucket.
+        //  The bucket links the source's blocks as they are, so there's nothing to upload
+        for (item_path, cid) in leaky.items().await? {
+            let item_path = item_path.strip_prefix("/").unwrap().to_path_buf();
+            if !item_path.starts_with(&local_dest) {
+                continue;
+            }
+            pull_file(&leaky, &item_path, &item_path).await?;
+            change_log.insert(item_path, (cid, ChangeType::Linked));
+        }
+        for dir in leaky.empty_dirs().await? {
+            let dir = dir.strip_prefix("/").unwrap().to_path_buf();
+            if dir.starts_with(&local_dest) {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+
+        utils::save_on_disk(&mut leaky, &change_log).await?;
+    }
+
+    let root_cid = push().await?;
+    Ok(root_cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CpRemoteError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("push error: {0}")]
+    Push(#[from] PushError),
+    #[error("expected <cid>:<path>, got {0}")]
+    InvalidRemotePath(String),
+    #[error("destination already exists in the working tree: {0}")]
+    PathExists(PathBuf),
+    #[error("the source root is missing {0} blocks on the remote")]
+    MissingBlocks(usize),
+}
+
+#[cfg(test)]
+mod test {
+    use url::Url;
+
+    use super::*;
+    use crate::ops::utils::test::{FakeApi, TestTree};
+
+    #[tokio::test]
+    async fn cp_remote_between_buckets() {
+        let tree = TestTree::init("cp-remote").await;
+
+        // Another bucket on the same ipfs node, behind its own api
+        let other_api = FakeApi::serve().await;
+        let mut source = Leaky::new(
+            Url::parse("http://localhost:5001").unwrap(),
+            other_api.url(),
+        )
+        .unwrap();
+        source.init().await.unwrap();
+        source
+            .add(&PathBuf::from("/dir/a"), "a".as_bytes(), None, false)
+            .await
+            .unwrap();
+        source
+            .add(&PathBuf::from("/dir/b"), "b".as_bytes(), None, false)
+            .await
+            .unwrap();
+        source.push().await.unwrap();
+        let source_items = source.items().await.unwrap();
+
+        let remote_path = format!("{}:dir", source.cid().unwrap())
+            .parse::<RemotePath>()
+            .unwrap();
+        let cid = cp_remote(remote_path, PathBuf::from("copied"))
+            .await
+            .unwrap();
+        assert_eq!(tree.api.root(), Some(cid));
+        assert_eq!(tree.read("copied/a"), "a");
+        assert_eq!(tree.read("copied/b"), "b");
+
+        // The copy links the source's data, and is already pushed
+        let (remote, _) = utils::pull_remote(None).await.unwrap();
+        let copied_items = remote.items().await.unwrap();
+        let (_, change_log) = utils::load_on_disk().await.unwrap();
+        for ((source_path, source_cid), (copied_path, copied_cid)) in
+            source_items.iter().zip(copied_items.iter())
+        {
+            assert_eq!(
+                copied_path,
+                &PathBuf::from("/copied").join(source_path.strip_prefix("/dir").unwrap())
+            );
+            assert_eq!(copied_cid, source_cid);
+            let local_path = copied_path.strip_prefix("/").unwrap().to_path_buf();
+            assert_eq!(
+                change_log.get(&local_path),
+                Some(&(*source_cid, ChangeType::Base))
+            );
+        }
+        assert_eq!(copied_items.len(), 2);
+
+        // Nothing else to send
+        assert_eq!(push().await.unwrap(), cid);
+
+        // Paths that leave the bucket are turned away, on either side
+        assert!(matches!(
+            format!("{}:../dir", source.cid().unwrap()).parse::<RemotePath>(),
+            Err(CpRemoteError::InvalidRemotePath(_))
+        ));
+        let remote_path = format!("{}:dir", source.cid().unwrap())
+            .parse::<RemotePath>()
+            .unwrap();
+        assert!(matches!(
+            cp_remote(remote_path, PathBuf::from("../escaped")).await,
+            Err(CpRemoteError::Default(_))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeSet;
 use std::path::PathBuf;
 
 use leaky_common::prelude::*;
@@ -5,12 +6,67 @@ use leaky_common::prelude::*;
 use super::change_log::{ChangeLog, ChangeType};
 use super::utils;
 
-pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, DiffError> {
+/// Knobs for how the working tree is read
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Follow symlinks instead of skipping them
+    pub follow_symlinks: bool,
+    /// Files larger than this many bytes are rejected
+    pub max_file_size: Option<u64>,
+    /// Leave files over the limit out of the diff instead of failing
+    pub skip_large: bool,
+}
+
+pub async fn diff(
+    leaky: &Leaky,
+    base: &mut ChangeLog,
+    options: &DiffOptions,
+) -> Result<ChangeLog, DiffError> {
     let base: &mut ChangeLog = base;
     let mut update = base.clone();
-    let next = utils::fs_tree()?;
+    let next = utils::fs_tree(options.follow_symlinks)?;
     let default_hash = Cid::default();
 
+    // Catch anything that would collide with names reserved within the bucket
+    //  before we start mutating anything
+    let reserved = next
+        .iter()
+        .map(|(_tree, path)| path)
+        .filter(|path| {
+            path.file_name().map_or(false, |name| {
+                RESERVED_NAMES.contains(&&*name.to_string_lossy())
+            })
+        })
+        .collect::<Vec<_>>();
+    if !reserved.is_empty() {
+        return Err(DiffError::ReservedNames(reserved));
+    }
+
+    // Same for anything over the size limit, going off the metadata so we never hash them
+    let mut large = vec![];
+    if let Some(max_file_size) = options.max_file_size {
+        for (tree, path) in next.iter() {
+            if tree.is_dir() {
+                continue;
+            }
+            let size = std::fs::metadata(&path)?.len();
+            if size > max_file_size {
+                large.push((path, size));
+            }
+        }
+    }
+    if !large.is_empty() && !options.skip_large {
+        return Err(DiffError::FilesTooLarge(large));
+    }
+    for (path, size) in large.iter() {
+        utils::pretty_print(format!("skipping {} ({} bytes)", path.display(), size));
+    }
+    // Skipped files are left as they were in the base
+    let skipped = large
+        .into_iter()
+        .map(|(path, _size)| path)
+        .collect::<BTreeSet<_>>();
+
     // Insert the root directory hash into the change_log for comparison
     // This should always just get matched out and removed
     base.insert(PathBuf::from(""), (default_hash, ChangeType::Base));
@@ -34,7 +90,7 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
                 if base_path < next_path {
                     if !base_path.is_dir() {
                         match base_type {
-                            ChangeType::Added { .. } => {
+                            ChangeType::Added { .. } | ChangeType::Linked => {
                                 update.remove(&base_path);
                             }
                             _ => {
@@ -49,7 +105,7 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
                 // If next comes before base then the file was added
                 // strip off the next object and log the addition
                 if next_path < base_path {
-                    if !next_path.is_dir() {
+                    if !next_path.is_dir() && !skipped.contains(&next_path) {
                         let hash = utils::hash_file(&next_path, leaky).await?;
                         update.insert(
                             next_path.clone(),
@@ -64,14 +120,15 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
                 if next_path == base_path {
                     // These are either both files or both directories
                     // If they are both files then we need to compare hashes
-                    if !next_tree.is_dir() {
+                    if !next_tree.is_dir() && !skipped.contains(&next_path) {
                         // If the hashes are different then the file was modified
                         // strip off the next object and log the modification
                         let next_hash = utils::hash_file(&next_path, leaky).await?;
                         if base_hash != &next_hash {
                             match base_type {
-                                // If it has been added before, then it was modified
-                                ChangeType::Added { .. } => {
+                                // If it has been added before, then it was modified.
+                                //  A linked file with new data needs uploading like any other
+                                ChangeType::Added { .. } | ChangeType::Linked => {
                                     update.insert(
                                         base_path.clone(),
                                         (next_hash, ChangeType::Added { modified: true }),
@@ -101,7 +158,7 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
 
             // Theres more new files than old, so this file was added
             (Some((next_tree, next_path)), None) => {
-                if !next_tree.is_dir() {
+                if !next_tree.is_dir() && !skipped.contains(&next_path) {
                     let hash = utils::hash_file(&next_path, leaky).await?;
                     update.insert(
                         next_path.clone(),
@@ -116,7 +173,7 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
             (None, Some((base_path, (_base_hash, base_type)))) => {
                 if !base_path.is_dir() {
                     match base_type {
-                        ChangeType::Added { .. } => {
+                        ChangeType::Added { .. } | ChangeType::Linked => {
                             update.remove(&base_path);
                         }
                         _ => {
@@ -153,4 +210,103 @@ pub enum DiffError {
     PathDoesNotExist(PathBuf),
     #[error("path is a directory")]
     PathIsDirectory(PathBuf),
+    #[error(
+        "reserved names can't be added, rename or remove: {}",
+        .0.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    ReservedNames(Vec<PathBuf>),
+    #[error(
+        "files over the size limit, remove them or use --skip-large: {}",
+        .0.iter().map(|(p, size)| format!("{} ({} bytes)", p.display(), size)).collect::<Vec<_>>().join(", ")
+    )]
+    FilesTooLarge(Vec<(PathBuf, u64)>),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, push, AddError};
+
+    #[tokio::test]
+    async fn reserved_names() {
+        let tree = TestTree::init("reserved-names").await;
+        tree.write("fine", "fine");
+        tree.write(".metadata", "reserved");
+        tree.write("dir/.default", "reserved");
+
+        let state_files = || {
+            [
+                utils::DEFAULT_STATE_NAME,
+                utils::DEFAULT_CACHE_NAME,
+                utils::DEFAULT_CHAGE_LOG_NAME,
+            ]
+            .map(|name| std::fs::read(PathBuf::from(utils::DEFAULT_LOCAL_DIR).join(name)).unwrap())
+        };
+        let before = state_files();
+
+        // Turned away before anything reaches the bucket, which would panic on them
+        match add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        {
+            Err(AddError::Diff(DiffError::ReservedNames(paths))) => {
+                assert_eq!(
+                    paths,
+                    vec![PathBuf::from(".metadata"), PathBuf::from("dir/.default")]
+                );
+            }
+            _ => panic!("add took a reserved name"),
+        }
+        assert_eq!(state_files(), before);
+    }
+
+    #[tokio::test]
+    async fn max_file_size() {
+        let tree = TestTree::init("max-file-size").await;
+        tree.write("small", "ok");
+        tree.write("big", "way over the limit");
+        let options = DiffOptions {
+            max_file_size: Some(4),
+            ..Default::default()
+        };
+
+        // Nothing gets staged while a file is over the limit
+        match add(false, false, options.clone(), DEFAULT_ADD_CONCURRENCY).await {
+            Err(AddError::Diff(DiffError::FilesTooLarge(large))) => {
+                assert_eq!(large, vec![(PathBuf::from("big"), 18)]);
+            }
+            _ => panic!("add took a file over the limit"),
+        }
+        let (_, change_log) = utils::load_on_disk().await.unwrap();
+        assert!(change_log.is_empty());
+
+        // Unless it's skipped, in which case it never reaches the bucket
+        let options = DiffOptions {
+            skip_large: true,
+            ..options
+        };
+        add(false, false, options, DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+        let (_, change_log) = utils::load_on_disk().await.unwrap();
+        assert_eq!(
+            change_log.keys().cloned().collect::<Vec<_>>(),
+            vec![PathBuf::from("small")]
+        );
+        push().await.unwrap();
+        let (remote, _) = utils::pull_remote(None).await.unwrap();
+        let items = remote
+            .items()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, _cid)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec![PathBuf::from("/small")]);
+    }
 }
@@ -0,0 +1,102 @@
+use std::fmt::Display;
+
+use leaky_common::prelude::*;
+use serde::Serialize;
+
+use super::utils;
+
+/// A version of the bucket, and the one it was built on
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub root_cid: String,
+    /// Unset for the bucket's first version
+    pub previous_cid: Option<String>,
+    /// Version of leaky that wrote the manifest
+    pub version: String,
+}
+
+impl Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.previous_cid {
+            Some(previous_cid) => write!(f, "{} <- {}", self.root_cid, previous_cid),
+            None => write!(f, "{}", self.root_cid),
+        }
+    }
+}
+
+/// List the versions leading up to the local state, newest first
+pub async fn history(limit: Option<usize>) -> Result<Vec<HistoryEntry>, HistoryError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let entries = leaky
+        .history(limit)
+        .await?
+        .into_iter()
+        .map(|(cid, manifest)| {
+            let previous = *manifest.previous();
+            HistoryEntry {
+                root_cid: cid.to_string(),
+                previous_cid: (previous != Cid::default()).then(|| previous.to_string()),
+                version: manifest.version().version().to_string(),
+            }
+        })
+        .collect();
+    Ok(entries)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HistoryError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, push, DiffOptions};
+
+    #[tokio::test]
+    async fn history_json() {
+        let tree = TestTree::init("history").await;
+        let mut root = None;
+        for i in 0..3 {
+            tree.write("foo", &format!("v{}", i));
+            add(
+                false,
+                false,
+                DiffOptions::default(),
+                DEFAULT_ADD_CONCURRENCY,
+            )
+            .await
+            .unwrap();
+            root = Some(push().await.unwrap());
+        }
+
+        let entries = history(None).await.unwrap();
+        // At least the first manifest, and one for each push, newest first
+        assert!(entries.len() >= 4);
+        assert_eq!(entries[0].root_cid, root.unwrap().to_string());
+        for pair in entries.windows(2) {
+            assert_eq!(pair[0].previous_cid.as_ref(), Some(&pair[1].root_cid));
+        }
+        assert_eq!(entries.last().unwrap().previous_cid, None);
+
+        let json = serde_json::to_value(&entries).unwrap();
+        let json = json.as_array().unwrap();
+        assert_eq!(json.len(), entries.len());
+        assert_eq!(json[0]["root_cid"], entries[0].root_cid);
+        assert_eq!(
+            json[0]["previous_cid"].as_str(),
+            entries[0].previous_cid.as_deref()
+        );
+        assert_eq!(json[0]["version"], entries[0].version);
+        assert!(json.last().unwrap()["previous_cid"].is_null());
+
+        let limited = history(Some(2)).await.unwrap();
+        assert_eq!(limited.len(), 2);
+        assert_eq!(limited[0].root_cid, entries[0].root_cid);
+        assert_eq!(limited[1].root_cid, entries[1].root_cid);
+    }
+}
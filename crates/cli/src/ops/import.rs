@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+
+use super::change_log::ChangeType;
+use super::pull::{pull_file, PullError};
+use super::utils;
+
+/// Import a UnixFS directory from the node into the bucket at a path,
+///  writing the imported files into the working tree
+pub async fn import_ipfs(cid: Cid, path: PathBuf) -> Result<Cid, ImportError> {
+    let _lock = utils::lock_on_disk()?;
+    let (mut leaky, mut change_log) = utils::load_on_disk().await?;
+
+    let abs_path = utils::bucket_path(&path)?;
+    let local_path = utils::local_path(&path)?;
+    if !local_path.as_os_str().is_empty() && local_path.exists() {
+        return Err(ImportError::PathExists(local_path));
+    }
+
+    leaky.import_unixfs(&abs_path, &cid).await?;
+
+    for (abs_item_path, cid) in leaky.items().await? {
+        let item_path = abs_item_path.strip_prefix("/").unwrap().to_path_buf();
+        if !item_path.starts_with(&local_path) || change_log.contains_key(&item_path) {
+            continue;
+        }
+        pull_file(&leaky, &item_path, &item_path).await?;
+        // Whatever produced the UnixFS dag may have hashed or chunked it differently.
+        //  Files are relinked under the cid we'd compute for the local copy, so they
+        //  diff and pull like anything else
+        let mut hash = utils::hash_file(&item_path, &leaky).await?;
+        if hash != cid {
+            let file = utils::open_file(&item_path)?;
+            hash = leaky.add(&abs_item_path, file, None, false).await?;
+        }
+        // The data is already on the node, so push only needs to send the links
+        change_log.insert(item_path, (hash, ChangeType::Linked));
+    }
+    for dir in leaky.empty_dirs().await? {
+        let dir = dir.strip_prefix("/").unwrap().to_path_buf();
+        if dir.starts_with(&local_path) {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+
+    let root_cid = leaky.cid()?;
+    utils::save_on_disk(&mut leaky, &change_log).await?;
+
+    Ok(root_cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("path already exists in the working tree: {0}")]
+    PathExists(PathBuf),
+}
+
+#[cfg(test)]
+mod test {
+    use ipfs_api_backend_hyper::{IpfsApi, IpfsClient};
+
+    use super::*;
+    use crate::ops::push;
+    use crate::ops::utils::test::TestTree;
+
+    #[tokio::test]
+    async fn import_ipfs_dir() {
+        let tree = TestTree::init("import-ipfs").await;
+
+        // Seed a UnixFS directory through MFS, which hashes and chunks differently than we do
+        let ipfs = IpfsClient::default();
+        let root = format!("/leaky-import-{}", rand::random::<u64>());
+        ipfs.files_mkdir(&format!("{}/sub", root), true)
+            .await
+            .unwrap();
+        ipfs.files_write(&format!("{}/foo", root), true, true, "foo".as_bytes())
+            .await
+            .unwrap();
+        ipfs.files_write(&format!("{}/sub/bar", root), true, true, "bar".as_bytes())
+            .await
+            .unwrap();
+        let dir_cid = Cid::try_from(ipfs.files_stat(&root).await.unwrap().hash).unwrap();
+
+        import_ipfs(dir_cid, PathBuf::from("imported"))
+            .await
+            .unwrap();
+        assert_eq!(tree.read("imported/foo"), "foo");
+        assert_eq!(tree.read("imported/sub/bar"), "bar");
+
+        // Relinked under our own hashes, and staged without anything to upload
+        let (leaky, change_log) = utils::load_on_disk().await.unwrap();
+        let items = leaky.items().await.unwrap();
+        assert_eq!(items.len(), 2);
+        for (path, cid) in items.iter() {
+            let local_path = path.strip_prefix("/").unwrap().to_path_buf();
+            assert_eq!(utils::hash_file(&local_path, &leaky).await.unwrap(), *cid);
+            assert_eq!(
+                change_log.get(&local_path),
+                Some(&(*cid, ChangeType::Linked))
+            );
+        }
+
+        // Already there
+        assert!(matches!(
+            import_ipfs(dir_cid, PathBuf::from("imported")).await,
+            Err(ImportError::PathExists(_))
+        ));
+
+        let root_cid = push().await.unwrap();
+        assert_eq!(tree.api.root(), Some(root_cid));
+        let (remote, _) = utils::pull_remote(None).await.unwrap();
+        assert_eq!(remote.items().await.unwrap(), items);
+        let (_, change_log) = utils::load_on_disk().await.unwrap();
+        assert!(change_log
+            .values()
+            .all(|(_hash, diff_type)| diff_type == &ChangeType::Base));
+
+        ipfs.files_rm(&root, true).await.unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// What the CLI resolved its local state and config to
+#[derive(Debug)]
+pub struct Info {
+    pub local_dir: PathBuf,
+    pub config: utils::OnDiskConfig,
+    pub cid: Cid,
+    pub previous_cid: Cid,
+    pub change_log_entries: usize,
+}
+
+impl Display for Info {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "local dir: {}", self.local_dir.display())?;
+        writeln!(f, "ipfs rpc: {}", self.config.ipfs_rpc_url)?;
+        writeln!(f, "leaky api: {}", self.config.leaky_api_url)?;
+        writeln!(f, "chunker: {}", self.config.add_options.chunker)?;
+        writeln!(f, "raw leaves: {}", self.config.add_options.raw_leaves)?;
+        writeln!(f, "compress: {}", self.config.compress)?;
+        writeln!(f, "cid: {}", self.cid)?;
+        writeln!(f, "previous cid: {}", self.previous_cid)?;
+        write!(f, "change log entries: {}", self.change_log_entries)
+    }
+}
+
+pub async fn info() -> Result<Info, InfoError> {
+    let local_dir = std::fs::canonicalize(utils::DEFAULT_LOCAL_DIR)
+        .map_err(|_| InfoError::Default(anyhow::anyhow!("No leaky directory found")))?;
+    let config = utils::load_config()?;
+    let (leaky, change_log) = utils::load_on_disk().await?;
+    let manifest = leaky.manifest()?;
+
+    Ok(Info {
+        local_dir,
+        config,
+        cid: leaky.cid()?,
+        previous_cid: *manifest.previous(),
+        change_log_entries: change_log.len(),
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InfoError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, DiffOptions};
+
+    #[tokio::test]
+    async fn info_shows_remote_and_cid() {
+        let tree = TestTree::init("info").await;
+        tree.write("foo", "foo");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+
+        let output = info().await.unwrap().to_string();
+        let (leaky, _) = utils::load_on_disk().await.unwrap();
+        assert!(output.contains(&format!("leaky api: {}", tree.api.url())));
+        assert!(output.contains(&format!("cid: {}", leaky.cid().unwrap())));
+        assert!(output.contains("change log entries: 1"));
+    }
+
+    #[tokio::test]
+    async fn info_without_bucket() {
+        let _tree = TestTree::empty("info-empty").await;
+        assert!(matches!(info().await, Err(InfoError::Default(_))));
+    }
+}
@@ -4,8 +4,14 @@ use leaky_common::prelude::*;
 
 use super::utils;
 
-pub async fn init(ipfs_rpc_url: Url, leaky_api_url: Url) -> Result<Cid, InitError> {
-    let mut leaky = utils::init_on_disk(ipfs_rpc_url, leaky_api_url, None).await?;
+pub async fn init(
+    ipfs_rpc_url: Url,
+    leaky_api_url: Url,
+    add_options: AddOptions,
+    compress: bool,
+) -> Result<Cid, InitError> {
+    let mut leaky =
+        utils::init_on_disk(ipfs_rpc_url, leaky_api_url, None, add_options, compress).await?;
     leaky.push().await?;
     let cid = leaky.cid()?;
     Ok(cid)
@@ -0,0 +1,35 @@
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// Describe a node or manifest block, either as a listing of its entries
+///  or as pretty printed dag-json
+pub async fn inspect(cid: Cid, json: bool) -> Result<String, InspectError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    if json {
+        return Ok(leaky.inspect_json(&cid).await?);
+    }
+    let map = match leaky.inspect(&cid).await? {
+        Ipld::Map(map) => map,
+        _ => return Err(InspectError::NotMap(cid)),
+    };
+    let lines = map
+        .iter()
+        .map(|(name, value)| match value {
+            Ipld::Link(link) => format!("{}: {}", name, link),
+            Ipld::Map(map) => format!("{}: ({} entries)", name, map.len()),
+            other => format!("{}: {:?}", name, other),
+        })
+        .collect::<Vec<_>>();
+    Ok(lines.join("\n"))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InspectError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("block is not a map: {0}")]
+    NotMap(Cid),
+}
@@ -0,0 +1,133 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+use serde::Serialize;
+
+use super::utils;
+
+/// A file or directory within a listing
+#[derive(Debug, Serialize)]
+pub struct LsEntry {
+    pub path: PathBuf,
+    pub cid: String,
+    pub is_dir: bool,
+    /// Only files carry objects
+    pub object: Option<Object>,
+}
+
+impl Display for LsEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let suffix = if self.is_dir { "/" } else { "" };
+        write!(f, "{}{} -> {}", self.path.display(), suffix, self.cid)
+    }
+}
+
+/// List a directory in the local state, or in some other root if `cid` is set.
+///  Listing another root only reads it, the local state is left alone
+pub async fn ls(path: PathBuf, cid: Option<Cid>, recursive: bool) -> Result<Vec<LsEntry>, LsError> {
+    let leaky = match cid {
+        Some(cid) => utils::pull_remote(Some(cid)).await?.0,
+        None => utils::load_on_disk().await?.0,
+    };
+    let path = PathBuf::from("/").join(path);
+
+    let mut entries = Vec::new();
+    let mut dirs = vec![path];
+    while let Some(dir) = dirs.pop() {
+        for (name, (link, object)) in leaky.ls(&dir).await? {
+            let entry_path = dir.join(&name);
+            // Files always carry an object, directories never do
+            let is_dir = object.is_none();
+            if is_dir && recursive {
+                dirs.push(entry_path.clone());
+            }
+            entries.push(LsEntry {
+                path: entry_path,
+                cid: link.to_string(),
+                is_dir,
+                object,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LsError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, push, DiffOptions};
+
+    #[tokio::test]
+    async fn ls_old_root() {
+        let tree = TestTree::init("ls-cid").await;
+        tree.write("dir/a", "a");
+        tree.write("top", "top");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let old_root = push().await.unwrap();
+        std::fs::remove_file("top").unwrap();
+        tree.write("dir/b", "b");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let new_root = push().await.unwrap();
+
+        let (remote, _) = utils::pull_remote(Some(old_root)).await.unwrap();
+        let (_name, (dir_cid, _object)) = remote
+            .ls(&PathBuf::from("/"))
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|(name, _)| name == "dir")
+            .unwrap();
+        let old = ls(PathBuf::from("/"), Some(old_root), false).await.unwrap();
+        assert_eq!(old.len(), 2);
+        assert_eq!(old[0].to_string(), format!("/dir/ -> {}", dir_cid));
+        assert!(old[0].is_dir && old[0].object.is_none());
+        assert!(!old[1].is_dir && old[1].object.is_some());
+
+        let old = ls(PathBuf::from(""), Some(old_root), true).await.unwrap();
+        let old_paths = old
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            old_paths,
+            ["/dir", "/dir/a", "/top"].map(PathBuf::from).to_vec()
+        );
+
+        // Without a cid it's the local state, which listing an old root didn't touch
+        let current = ls(PathBuf::from("dir"), None, false).await.unwrap();
+        assert_eq!(
+            current
+                .iter()
+                .map(|entry| entry.path.clone())
+                .collect::<Vec<_>>(),
+            ["/dir/a", "/dir/b"].map(PathBuf::from).to_vec()
+        );
+        let (leaky, _) = utils::load_on_disk().await.unwrap();
+        assert_eq!(leaky.cid().unwrap(), new_root);
+    }
+}
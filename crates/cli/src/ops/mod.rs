@@ -1,16 +1,40 @@
 mod add;
+mod auto_tag;
+mod cat;
 mod change_log;
+mod cp_remote;
 mod diff;
+mod history;
+mod import;
+mod info;
 mod init;
+mod inspect;
+mod ls;
 mod pull;
 mod push;
+mod rm;
 mod stat;
 mod tag;
+mod touch;
+mod tree;
 pub mod utils;
+mod verify;
 
 pub use add::{add, AddError};
+pub use cat::{cat, Cat, CatError};
+pub use cp_remote::{cp_remote, CpRemoteError, RemotePath};
+pub use diff::DiffOptions;
+pub use history::{history, HistoryError};
+pub use import::{import_ipfs, ImportError};
+pub use info::{info, InfoError};
 pub use init::{init, InitError};
-pub use pull::{pull, PullError};
+pub use inspect::{inspect, InspectError};
+pub use ls::{ls, LsError};
+pub use pull::{pull, pull_into, pull_path, pull_since, PullError};
 pub use push::{push, PushError};
-pub use stat::{stat, StatError};
-pub use tag::{tag, TagError};
+pub use rm::{rm, RmError};
+pub use stat::{cache_stats, stat, StatError};
+pub use tag::{tag, MetadataSource, TagError};
+pub use touch::{touch, TouchError};
+pub use tree::{tree, TreeError};
+pub use verify::{verify_remote, VerifyError, DEFAULT_VERIFY_CONCURRENCY};
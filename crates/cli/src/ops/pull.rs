@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::path::PathBuf;
 
@@ -7,6 +8,9 @@ use super::change_log::{ChangeLog, ChangeType};
 
 use super::utils;
 
+// How many files to write between saving the pull checkpoint
+const PULL_CHECKPOINT_INTERVAL: usize = 64;
+
 pub async fn file_needs_pull(leaky: &Leaky, path: &PathBuf, cid: &Cid) -> Result<bool, PullError> {
     if !path.exists() {
         return Ok(true);
@@ -22,12 +26,12 @@ pub async fn file_needs_pull(leaky: &Leaky, path: &PathBuf, cid: &Cid) -> Result
     }
 }
 
-pub async fn pull_file(leaky: &Leaky, path: &PathBuf) -> Result<(), PullError> {
+pub async fn pull_file(leaky: &Leaky, path: &PathBuf, out_path: &PathBuf) -> Result<(), PullError> {
     let data_vec = leaky.cat(&PathBuf::from("/").join(path)).await?;
-    let mut object_path = path.clone();
+    let mut object_path = out_path.clone();
     object_path.pop();
     std::fs::create_dir_all(object_path)?;
-    let mut file = std::fs::File::create(path)?;
+    let mut file = std::fs::File::create(out_path)?;
     file.write_all(data_vec.as_slice())?;
     Ok(())
 }
@@ -38,9 +42,12 @@ fn rm_file(path: &PathBuf) -> Result<(), PullError> {
 }
 
 pub async fn pull() -> Result<Cid, PullError> {
+    let _lock = utils::lock_on_disk()?;
     let (mut leaky, _) = utils::load_on_disk().await?;
     let root_cid = leaky.pull_root_cid().await?;
     leaky.pull(&root_cid).await?;
+    // Files written by an interrupted pull of this root don't need checking again
+    let mut checkpoint = utils::load_pull_checkpoint(&root_cid)?;
 
     let pulled_items = leaky
         .items()
@@ -55,7 +62,8 @@ pub async fn pull() -> Result<Cid, PullError> {
         change_log.insert(path.clone(), (*cid, ChangeType::Base));
     }
 
-    let current_fs_tree = utils::fs_tree()?;
+    // Symlinks never come from the bucket, so leave them be
+    let current_fs_tree = utils::fs_tree(false)?;
 
     let mut pi_iter = pulled_items.iter();
     let mut ci_iter = current_fs_tree.iter();
@@ -78,6 +86,8 @@ pub async fn pull() -> Result<Cid, PullError> {
                     to_pull.push((pi_path, pi_cid));
                 } else if pi_path > &ci_path {
                     to_prune.push(ci_path);
+                } else if checkpoint.pulled.get(pi_path) == Some(pi_cid) {
+                    // Already written before the last pull was interrupted
                 } else if file_needs_pull(&leaky, &ci_path, pi_cid).await?
                     && *pi_cid != Cid::default()
                 {
@@ -100,18 +110,152 @@ pub async fn pull() -> Result<Cid, PullError> {
         }
     }
 
-    for item in to_pull {
-        pull_file(&leaky, item.0).await?;
+    for (i, item) in to_pull.into_iter().enumerate() {
+        pull_file(&leaky, item.0, item.0).await?;
+        checkpoint.pulled.insert(item.0.clone(), *item.1);
+        if (i + 1) % PULL_CHECKPOINT_INTERVAL == 0 {
+            utils::save_pull_checkpoint(&checkpoint)?;
+        }
     }
 
     for path in to_prune {
         rm_file(&path)?;
     }
 
+    // Recreate any empty directories
+    for path in leaky.empty_dirs().await? {
+        std::fs::create_dir_all(path.strip_prefix("/").unwrap())?;
+    }
+
     utils::save_on_disk(&mut leaky, &change_log).await?;
+    utils::clear_pull_checkpoint()?;
     Ok(root_cid)
 }
 
+/// Pull the latest root, only writing and removing the files that changed since
+///  `since`. Everything else in the working tree is trusted and left untouched
+pub async fn pull_since(since: Cid) -> Result<Cid, PullError> {
+    let _lock = utils::lock_on_disk()?;
+    let (mut leaky, _) = utils::load_on_disk().await?;
+    let (base, _) = utils::pull_remote(Some(since)).await?;
+    let root_cid = leaky.pull_root_cid().await?;
+    leaky.pull(&root_cid).await?;
+
+    let base_items = base.items().await?.into_iter().collect::<BTreeMap<_, _>>();
+    let pulled_items = leaky.items().await?;
+
+    let mut change_log = ChangeLog::new();
+    for (path, cid) in pulled_items.iter() {
+        let local_path = path.strip_prefix("/").unwrap().to_path_buf();
+        if base_items.get(path) != Some(cid) {
+            pull_file(&leaky, &local_path, &local_path).await?;
+        }
+        change_log.insert(local_path, (*cid, ChangeType::Base));
+    }
+    for path in base_items.keys() {
+        let local_path = path.strip_prefix("/").unwrap().to_path_buf();
+        if !change_log.contains_key(&local_path) && local_path.exists() {
+            rm_file(&local_path)?;
+        }
+    }
+    for path in leaky.empty_dirs().await? {
+        std::fs::create_dir_all(path.strip_prefix("/").unwrap())?;
+    }
+
+    utils::save_on_disk(&mut leaky, &change_log).await?;
+    Ok(root_cid)
+}
+
+/// Pull a single file or directory from the latest root, leaving the rest of the working
+///  tree alone. Pulled files are copied into the local bucket, and staged if they differ.
+///  Refuses to overwrite files that have changed locally
+pub async fn pull_path(path: PathBuf) -> Result<Cid, PullError> {
+    let _lock = utils::lock_on_disk()?;
+    let (mut leaky, mut change_log) = utils::load_on_disk().await?;
+    let (remote, _) = utils::pull_remote(None).await?;
+
+    let abs_path = utils::bucket_path(&path)?;
+    let local_items = leaky.items().await?.into_iter().collect::<BTreeMap<_, _>>();
+    let remote_items = remote
+        .items()
+        .await?
+        .into_iter()
+        .filter(|(item_path, _cid)| item_path.starts_with(&abs_path))
+        .collect::<Vec<_>>();
+    let remote_dirs = remote
+        .empty_dirs()
+        .await?
+        .into_iter()
+        .filter(|dir| dir.starts_with(&abs_path))
+        .collect::<Vec<_>>();
+    if remote_items.is_empty() && remote_dirs.is_empty() {
+        return Err(PullError::Leaky(LeakyError::PathDoesNotExist(abs_path)));
+    }
+
+    // Don't overwrite anything that's changed since it was last pulled or pushed
+    let mut local_changes = vec![];
+    for (item_path, cid) in remote_items.iter() {
+        let local_path = item_path.strip_prefix("/").unwrap().to_path_buf();
+        if !local_path.is_file() {
+            continue;
+        }
+        let hash = utils::hash_file(&local_path, &leaky).await?;
+        if hash == *cid {
+            continue;
+        }
+        match change_log.get(&local_path) {
+            Some((base_hash, ChangeType::Base)) if *base_hash == hash => {}
+            _ => local_changes.push(local_path),
+        }
+    }
+    if !local_changes.is_empty() {
+        return Err(PullError::LocalChanges(local_changes));
+    }
+
+    for (item_path, cid) in remote_items {
+        let local_path = item_path.strip_prefix("/").unwrap().to_path_buf();
+        pull_file(&remote, &local_path, &local_path).await?;
+        let hash = utils::hash_file(&local_path, &leaky).await?;
+        let change = if local_items.get(&item_path) == Some(&cid) {
+            ChangeType::Base
+        } else {
+            leaky.copy_from(&remote, &item_path, &item_path).await?;
+            match change_log.get(&local_path) {
+                None | Some((_, ChangeType::Added { .. })) => ChangeType::Added { modified: false },
+                Some(_) => ChangeType::Modified,
+            }
+        };
+        change_log.insert(local_path, (hash, change));
+    }
+    for dir in remote_dirs {
+        std::fs::create_dir_all(dir.strip_prefix("/").unwrap())?;
+    }
+
+    let cid = leaky.cid()?;
+    utils::save_on_disk(&mut leaky, &change_log).await?;
+    Ok(cid)
+}
+
+/// Materialize an arbitrary root into a directory without touching the local state
+pub async fn pull_into(cid: Cid, dir: PathBuf) -> Result<Cid, PullError> {
+    let (leaky, _) = utils::pull_remote(Some(cid)).await?;
+
+    if dir.exists() && std::fs::read_dir(&dir)?.next().is_some() {
+        return Err(PullError::DirectoryNotEmpty(dir));
+    }
+    std::fs::create_dir_all(&dir)?;
+
+    for (path, _cid) in leaky.items().await? {
+        let path = path.strip_prefix("/").unwrap().to_path_buf();
+        pull_file(&leaky, &path, &dir.join(&path)).await?;
+    }
+    for path in leaky.empty_dirs().await? {
+        std::fs::create_dir_all(dir.join(path.strip_prefix("/").unwrap()))?;
+    }
+
+    Ok(cid)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum PullError {
     #[error("default error: {0}")]
@@ -126,4 +270,231 @@ pub enum PullError {
     Leaky(#[from] LeakyError),
     #[error("path is a directory: {0}")]
     PathIsDirectory(PathBuf),
+    #[error("directory is not empty: {0}")]
+    DirectoryNotEmpty(PathBuf),
+    #[error(
+        "local changes would be overwritten:\n{}",
+        .0.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join("\n")
+    )]
+    LocalChanges(Vec<PathBuf>),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, push, DiffOptions};
+
+    #[tokio::test]
+    async fn pull_into_old_version() {
+        let tree = TestTree::init("pull-into").await;
+        tree.write("foo", "v1");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let old_cid = push().await.unwrap();
+        tree.write("foo", "v2");
+        tree.write("bar", "bar");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let new_cid = push().await.unwrap();
+        let (_, change_log) = utils::load_on_disk().await.unwrap();
+
+        let into = tree.path().with_file_name("leaky-test-pull-into-out");
+        let _ = std::fs::remove_dir_all(&into);
+        assert_eq!(pull_into(old_cid, into.clone()).await.unwrap(), old_cid);
+        assert_eq!(std::fs::read_to_string(into.join("foo")).unwrap(), "v1");
+        assert!(!into.join("bar").exists());
+
+        // The local state is left alone
+        let (leaky, after) = utils::load_on_disk().await.unwrap();
+        assert_eq!(leaky.cid().unwrap(), new_cid);
+        assert_eq!(after, change_log);
+        assert_eq!(tree.read("foo"), "v2");
+
+        assert!(matches!(
+            pull_into(old_cid, into.clone()).await,
+            Err(PullError::DirectoryNotEmpty(_))
+        ));
+        std::fs::remove_dir_all(&into).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pull_since_only_touches_changes() {
+        let tree = TestTree::init("pull-since").await;
+        tree.write("same", "same");
+        tree.write("changed", "v1");
+        tree.write("removed", "removed");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let since = push().await.unwrap();
+
+        // Someone else changes the bucket
+        let (mut other, _) = utils::pull_remote(None).await.unwrap();
+        other
+            .add(&PathBuf::from("/changed"), "v2".as_bytes(), None, false)
+            .await
+            .unwrap();
+        other
+            .add(&PathBuf::from("/new"), "new".as_bytes(), None, false)
+            .await
+            .unwrap();
+        other.rm(&PathBuf::from("/removed")).await.unwrap();
+        other.push().await.unwrap();
+        let root = tree.api.root().unwrap();
+
+        // A file that didn't change remotely is trusted, even if it differs locally
+        tree.write("same", "edited locally");
+
+        assert_eq!(pull_since(since).await.unwrap(), root);
+        assert_eq!(tree.read("same"), "edited locally");
+        assert_eq!(tree.read("changed"), "v2");
+        assert_eq!(tree.read("new"), "new");
+        assert!(!PathBuf::from("removed").exists());
+
+        let (leaky, change_log) = utils::load_on_disk().await.unwrap();
+        assert_eq!(leaky.cid().unwrap(), root);
+        let paths = change_log.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["changed", "new", "same"].map(PathBuf::from).to_vec()
+        );
+        assert!(change_log
+            .values()
+            .all(|(_hash, diff_type)| diff_type == &ChangeType::Base));
+    }
+
+    #[tokio::test]
+    async fn pull_path_partial() {
+        let tree = TestTree::init("pull-path").await;
+        tree.write("dir/a", "a");
+        tree.write("dir/b", "b");
+        tree.write("other", "other");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        push().await.unwrap();
+
+        // Someone else changes a file inside and outside of what we'll pull
+        let (mut remote, _) = utils::pull_remote(None).await.unwrap();
+        remote
+            .add(&PathBuf::from("/dir/a"), "a2".as_bytes(), None, false)
+            .await
+            .unwrap();
+        remote
+            .add(&PathBuf::from("/other"), "other2".as_bytes(), None, false)
+            .await
+            .unwrap();
+        remote.push().await.unwrap();
+
+        pull_path(PathBuf::from("dir")).await.unwrap();
+        assert_eq!(tree.read("dir/a"), "a2");
+        assert_eq!(tree.read("dir/b"), "b");
+        assert_eq!(tree.read("other"), "other");
+        let (_, change_log) = utils::load_on_disk().await.unwrap();
+        assert_eq!(
+            change_log
+                .get(&PathBuf::from("dir/a"))
+                .map(|(_, change)| change),
+            Some(&ChangeType::Modified)
+        );
+
+        // Local edits are left alone
+        tree.write("other", "mine");
+        assert!(matches!(
+            pull_path(PathBuf::from("other")).await,
+            Err(PullError::LocalChanges(paths)) if paths == vec![PathBuf::from("other")]
+        ));
+        assert_eq!(tree.read("other"), "mine");
+
+        // A nested file comes back along with its parents
+        std::fs::remove_dir_all("dir").unwrap();
+        pull_path(PathBuf::from("dir/b")).await.unwrap();
+        assert_eq!(tree.read("dir/b"), "b");
+        assert!(!PathBuf::from("dir/a").exists());
+
+        assert!(matches!(
+            pull_path(PathBuf::from("dir/../../other")).await,
+            Err(PullError::Default(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn pull_resumes_from_checkpoint() {
+        let tree = TestTree::init("pull-resume").await;
+        tree.write("a", "a1");
+        tree.write("b", "b1");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let old_root = push().await.unwrap();
+
+        let (mut remote, _) = utils::pull_remote(None).await.unwrap();
+        for path in ["/a", "/b"] {
+            let data = format!("{}2", &path[1..]);
+            remote
+                .add(
+                    &PathBuf::from(path),
+                    std::io::Cursor::new(data),
+                    None,
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+        remote.push().await.unwrap();
+        let root = tree.api.root().unwrap();
+        let (remote, _) = utils::pull_remote(None).await.unwrap();
+        let items = remote.items().await.unwrap();
+
+        // As if a pull of this root wrote `a` and then died. What's on disk is
+        //  trusted, so this marker has to survive the resumed pull
+        tree.write("a", "written before the interruption");
+        let mut checkpoint = utils::load_pull_checkpoint(&root).unwrap();
+        checkpoint.pulled.insert(PathBuf::from("a"), items[0].1);
+        utils::save_pull_checkpoint(&checkpoint).unwrap();
+
+        assert_eq!(pull().await.unwrap(), root);
+        assert_eq!(tree.read("a"), "written before the interruption");
+        assert_eq!(tree.read("b"), "b2");
+        assert!(!PathBuf::from(utils::DEFAULT_LOCAL_DIR)
+            .join(utils::DEFAULT_PULL_CHECKPOINT_NAME)
+            .exists());
+
+        // A checkpoint for some other root is ignored
+        checkpoint.cid = old_root;
+        utils::save_pull_checkpoint(&checkpoint).unwrap();
+        assert!(utils::load_pull_checkpoint(&root)
+            .unwrap()
+            .pulled
+            .is_empty());
+        utils::clear_pull_checkpoint().unwrap();
+    }
 }
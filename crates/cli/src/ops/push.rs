@@ -1,38 +1,53 @@
-use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
 
+use leaky_common::error::LeakyApiError;
 use leaky_common::prelude::*;
 
-use super::change_log::ChangeType;
+use super::change_log::{ChangeLog, ChangeType, DisplayableChangeLog};
 use super::utils;
 
+const PRE_PUSH_HOOK_NAME: &str = "pre-push";
+// How many times to rebase onto a remote root that moved before giving up
+const PUSH_REBASE_ATTEMPTS: usize = 3;
+
 pub async fn push() -> Result<Cid, PushError> {
+    let _lock = utils::lock_on_disk()?;
     let (mut leaky, change_log) = utils::load_on_disk().await?;
 
     let mut updates = change_log.clone();
 
     let root_cid = leaky.cid()?;
 
-    let mut changed = false;
+    let changed = change_log
+        .values()
+        .any(|(_hash, diff_type)| diff_type != &ChangeType::Base);
+    if !changed {
+        println!("No added changes to push");
+        return Ok(root_cid);
+    }
+
+    // Give the hook a chance to reject before anything is uploaded
+    run_pre_push_hook(&change_log, &root_cid)?;
+
     let change_log_iter = change_log.iter();
     // Iterate over the ChangeLog -- play updates against the base ... probably better to do this
     for (path, (hash, diff_type)) in change_log_iter {
         match diff_type {
             ChangeType::Added { .. } => {
-                changed = true;
-                let file = File::open(&path)?;
+                let file = utils::open_file(path)?;
                 leaky.add_data(file).await?;
                 updates.insert(path.clone(), (*hash, ChangeType::Base));
             }
 
             ChangeType::Modified => {
-                changed = true;
-                let file = File::open(&path)?;
+                let file = utils::open_file(path)?;
                 leaky.add_data(file).await?;
                 updates.insert(path.clone(), (*hash, ChangeType::Base));
             }
 
-            ChangeType::Removed => {
-                changed = true;
+            ChangeType::Removed | ChangeType::Linked => {
                 updates.insert(path.clone(), (*hash, ChangeType::Base));
             }
 
@@ -40,13 +55,23 @@ pub async fn push() -> Result<Cid, PushError> {
         }
     }
 
-    if !changed {
-        println!("No added changes to push");
-        return Ok(root_cid);
+    // Hold onto our version of the tree so we can carry objects across rebases
+    let local = leaky.clone();
+    let mut attempts = 0;
+    loop {
+        match leaky.push().await {
+            // Someone else pushed first, rebase our changes onto their root and try again
+            Err(LeakyError::LeakyApi(LeakyApiError::Conflict(_)))
+                if attempts < PUSH_REBASE_ATTEMPTS =>
+            {
+                attempts += 1;
+                println!("Remote root has moved, rebasing local changes");
+                rebase(&mut leaky, &local, &change_log).await?;
+            }
+            result => break result?,
+        }
     }
 
-    leaky.push().await?;
-
     let root_cid = leaky.cid()?;
 
     utils::save_on_disk(&mut leaky, &updates).await?;
@@ -54,6 +79,86 @@ pub async fn push() -> Result<Cid, PushError> {
     Ok(root_cid)
 }
 
+/// Run the user's pre-push hook, if there is one, handing it a summary of
+///  the staged changes on stdin. A non-zero exit aborts the push
+fn run_pre_push_hook(change_log: &ChangeLog, root_cid: &Cid) -> Result<(), PushError> {
+    let hook_path = PathBuf::from(utils::DEFAULT_LOCAL_DIR)
+        .join(utils::DEFAULT_HOOKS_DIR)
+        .join(PRE_PUSH_HOOK_NAME);
+    if !hook_path.is_file() {
+        return Ok(());
+    }
+
+    let mut child = Command::new(&hook_path)
+        .env("LEAKY_ROOT_CID", root_cid.to_string())
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let summary = DisplayableChangeLog(change_log.clone()).to_string();
+    // The hook doesn't have to read the summary, and may exit before we're done
+    //  writing it. Only its exit status decides the push
+    let mut stdin = child.stdin.take().unwrap();
+    match stdin.write_all(summary.as_bytes()) {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => {}
+    }
+    drop(stdin);
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(PushError::HookRejected(status));
+    }
+    Ok(())
+}
+
+/// Replay the changes in the change log on top of the latest remote root, then carry
+///  over any objects `local` updated more recently than the remote did
+async fn rebase(leaky: &mut Leaky, local: &Leaky, change_log: &ChangeLog) -> Result<(), PushError> {
+    let remote_cid = leaky.pull_root_cid().await?;
+    leaky.pull(&remote_cid).await?;
+
+    for (path, (_hash, diff_type)) in change_log.iter() {
+        let abs_path = PathBuf::from("/").join(path);
+        match diff_type {
+            ChangeType::Added { .. } | ChangeType::Modified | ChangeType::Linked => {
+                let file = utils::open_file(path)?;
+                leaky.add(&abs_path, file, None, true).await?;
+                if let Some(object) = local.get_object(&abs_path).await? {
+                    leaky.tag(&abs_path, object.metadata(), false).await?;
+                }
+            }
+            ChangeType::Removed => {
+                leaky.rm(&abs_path).await?;
+            }
+            _ => {}
+        }
+    }
+
+    // Objects can change without their data, through tag or touch. Whoever
+    //  updated an object last wins
+    for (path, _cid) in local.items().await? {
+        let local_object = match local.get_object(&path).await? {
+            Some(object) => object,
+            None => continue,
+        };
+        let remote_object = match leaky.get_object(&path).await {
+            Ok(Some(object)) => object,
+            // Removed on the remote, and we didn't touch its data
+            Ok(None) | Err(LeakyError::PathDoesNotExist(_)) | Err(LeakyError::PathNotDir(_)) => {
+                continue
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if remote_object.updated_at() >= local_object.updated_at() {
+            continue;
+        }
+        if remote_object.metadata() != local_object.metadata() {
+            leaky.tag(&path, local_object.metadata(), false).await?;
+        } else {
+            leaky.touch(&path).await?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum PushError {
     #[error("default error: {0}")]
@@ -70,4 +175,161 @@ pub enum PushError {
     PathPrefix(#[from] std::path::StripPrefixError),
     #[error("device error: {0}")]
     Leaky(#[from] LeakyError),
+    #[error("pre-push hook rejected the push: {0}")]
+    HookRejected(ExitStatus),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, tag, DiffOptions, MetadataSource};
+
+    #[tokio::test]
+    async fn push_rebases_on_conflict() {
+        let tree = TestTree::init("push-rebase").await;
+        tree.write("ours", "ours");
+        tree.write("shared", "shared");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        push().await.unwrap();
+
+        // Someone else pushes a file of their own
+        let (mut other, _) = utils::pull_remote(None).await.unwrap();
+        other
+            .add(&PathBuf::from("/theirs"), "theirs".as_bytes(), None, false)
+            .await
+            .unwrap();
+        other.push().await.unwrap();
+
+        // Meanwhile we add a file and retag one whose data we left alone
+        tree.write("mine", "mine");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let metadata = MetadataSource::Inline(r#"{"k": "v"}"#.to_string());
+        tag(PathBuf::from("shared"), metadata, false).await.unwrap();
+
+        // The api turns our first push away, as a server would on seeing a stale root
+        tree.api.conflict_next(1);
+        let pushes = tree.api.pushes();
+        let cid = push().await.unwrap();
+        assert_eq!(tree.api.root(), Some(cid));
+        assert_eq!(tree.api.pushes(), pushes + 1);
+
+        let (remote, _) = utils::pull_remote(None).await.unwrap();
+        let items = remote
+            .items()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, _cid)| path)
+            .collect::<Vec<_>>();
+        let expected = ["/mine", "/ours", "/shared", "/theirs"];
+        assert_eq!(items, expected.map(PathBuf::from).to_vec());
+        let object = remote
+            .get_object(&PathBuf::from("/shared"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            object.metadata().get("k"),
+            Some(&Ipld::String("v".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn push_gives_up_after_repeated_conflicts() {
+        let tree = TestTree::init("push-conflicts").await;
+        tree.write("foo", "foo");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let root = tree.api.root();
+
+        tree.api.conflict_next(PUSH_REBASE_ATTEMPTS + 1);
+        assert!(matches!(
+            push().await,
+            Err(PushError::Leaky(LeakyError::LeakyApi(
+                LeakyApiError::Conflict(_)
+            )))
+        ));
+        assert_eq!(tree.api.root(), root);
+    }
+
+    // Install an executable pre-push hook running `script`
+    fn install_hook(script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        let hooks_dir = PathBuf::from(utils::DEFAULT_LOCAL_DIR).join(utils::DEFAULT_HOOKS_DIR);
+        std::fs::create_dir_all(&hooks_dir).unwrap();
+        let hook_path = hooks_dir.join(PRE_PUSH_HOOK_NAME);
+        std::fs::write(&hook_path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn push_rejected_by_hook() {
+        let tree = TestTree::init("push-hook-reject").await;
+        tree.write("foo", "foo");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        install_hook("exit 1");
+
+        let root = tree.api.root();
+        let pushes = tree.api.pushes();
+        assert!(matches!(push().await, Err(PushError::HookRejected(_))));
+        assert_eq!(tree.api.root(), root);
+        assert_eq!(tree.api.pushes(), pushes);
+
+        // Nothing was marked as pushed
+        let (_, change_log) = utils::load_on_disk().await.unwrap();
+        assert!(matches!(
+            change_log.get(&PathBuf::from("foo")),
+            Some((_, ChangeType::Added { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn push_passed_by_hook() {
+        let tree = TestTree::init("push-hook-pass").await;
+        tree.write("foo", "foo");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let (leaky, _) = utils::load_on_disk().await.unwrap();
+        let root = leaky.cid().unwrap();
+        install_hook(r#"echo "$LEAKY_ROOT_CID" > hook-root; cat > hook-summary; exit 0"#);
+
+        let cid = push().await.unwrap();
+        assert_eq!(tree.api.root(), Some(cid));
+        assert_eq!(tree.read("hook-root").trim(), root.to_string());
+        assert!(tree.read("hook-summary").contains("foo"));
+    }
 }
@@ -0,0 +1,150 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+
+use super::change_log::ChangeType;
+use super::utils;
+
+fn confirm(path: &PathBuf) -> Result<bool, RmError> {
+    print!(
+        "Remove directory {} and all of its contents? [y/N] ",
+        path.display()
+    );
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(matches!(input.trim(), "y" | "Y" | "yes"))
+}
+
+pub async fn rm(path: PathBuf, recursive: bool, yes: bool) -> Result<Cid, RmError> {
+    let _lock = utils::lock_on_disk()?;
+    let (mut leaky, change_log) = utils::load_on_disk().await?;
+    let mut updates = change_log.clone();
+
+    let root_cid = leaky.cid()?;
+    let path = utils::bucket_path(&path)?;
+    let local_path = utils::local_path(&path)?;
+    if local_path.as_os_str().is_empty() {
+        return Err(RmError::RootPath);
+    }
+
+    // Files always carry an object, directories never do
+    let is_dir = match leaky.get_object(&path).await? {
+        Some(_) => false,
+        None => match leaky.ls(&path).await {
+            Ok(_) => true,
+            Err(LeakyError::PathDoesNotExist(_)) | Err(LeakyError::PathNotDir(_)) => {
+                return Err(RmError::PathDoesNotExist(path));
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+
+    if is_dir {
+        if !recursive {
+            return Err(RmError::PathIsDirectory(path));
+        }
+        if !yes && !confirm(&path)? {
+            println!("Aborted");
+            return Ok(root_cid);
+        }
+    }
+
+    leaky.rm(&path).await?;
+    let new_root_cid = leaky.cid()?;
+
+    // Mark everything at or beneath the path as removed
+    for (c_path, (_cid, change)) in change_log.iter() {
+        if !c_path.starts_with(&local_path) {
+            continue;
+        }
+        match change {
+            ChangeType::Added { .. } | ChangeType::Linked => {
+                updates.remove(c_path);
+            }
+            _ => {
+                updates.insert(c_path.clone(), (Cid::default(), ChangeType::Removed));
+            }
+        }
+    }
+
+    // Remove the local copy
+    if local_path.is_dir() {
+        std::fs::remove_dir_all(&local_path)?;
+    } else if local_path.exists() {
+        std::fs::remove_file(&local_path)?;
+    }
+
+    utils::save_on_disk(&mut leaky, &updates).await?;
+
+    Ok(new_root_cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RmError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("device error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("no such file or directory in the bucket: {0}")]
+    PathDoesNotExist(PathBuf),
+    #[error("{0} is a directory, use --recursive to remove it")]
+    PathIsDirectory(PathBuf),
+    #[error("refusing to remove the root of the bucket")]
+    RootPath,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, DiffOptions};
+
+    #[tokio::test]
+    async fn rm_file_and_dirs() {
+        let tree = TestTree::init("rm").await;
+        tree.write("top", "top");
+        tree.write("foo/bar", "bar");
+        tree.write("foo/baz/qux", "qux");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+
+        // Paths without a leading / are still within the bucket
+        rm(PathBuf::from("top"), false, false).await.unwrap();
+        assert!(!PathBuf::from("top").exists());
+        let (leaky, change_log) = utils::load_on_disk().await.unwrap();
+        assert!(leaky
+            .get_object(&PathBuf::from("/top"))
+            .await
+            .unwrap()
+            .is_none());
+        // Never pushed, so it's just dropped from the log
+        assert!(!change_log.contains_key(&PathBuf::from("top")));
+
+        assert!(matches!(
+            rm(PathBuf::from("foo"), false, true).await,
+            Err(RmError::PathIsDirectory(_))
+        ));
+        assert!(PathBuf::from("foo/baz/qux").exists());
+
+        rm(PathBuf::from("/foo"), true, true).await.unwrap();
+        assert!(!PathBuf::from("foo").exists());
+        let (leaky, change_log) = utils::load_on_disk().await.unwrap();
+        assert!(leaky.items().await.unwrap().is_empty());
+        assert!(change_log.is_empty());
+
+        assert!(matches!(
+            rm(PathBuf::from("missing"), false, true).await,
+            Err(RmError::PathDoesNotExist(_))
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use leaky_common::prelude::*;
+
 use super::change_log::DisplayableChangeLog as ChangeLog;
 use super::utils;
 
@@ -6,8 +8,16 @@ pub async fn stat() -> Result<ChangeLog, StatError> {
     Ok(ChangeLog(change_log))
 }
 
+pub async fn cache_stats() -> Result<CacheStats, StatError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let stats = leaky.cache_stats()?;
+    Ok(stats)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum StatError {
     #[error("default error: {0}")]
     Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
 }
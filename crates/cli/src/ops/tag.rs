@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::io::Read;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -8,15 +9,13 @@ use serde_json::Value;
 use super::change_log::ChangeType;
 use super::utils;
 
-fn clean_path(path: &PathBuf) -> PathBuf {
-    // Strip the / prefix
-    path.strip_prefix("/").unwrap().to_path_buf()
-}
-
 fn value_to_metadata(value: String) -> Result<BTreeMap<String, Ipld>, TagError> {
     let mut metadata = BTreeMap::new();
     let value: Value = serde_json::from_str(&value)?;
-    for (key, value) in value.as_object().unwrap() {
+    let object = value
+        .as_object()
+        .ok_or_else(|| TagError::Default(anyhow!("metadata must be a JSON object")))?;
+    for (key, value) in object {
         let ipld = match value {
             Value::String(s) => Ipld::String(s.clone()),
             Value::Number(n) => {
@@ -37,13 +36,39 @@ fn value_to_metadata(value: String) -> Result<BTreeMap<String, Ipld>, TagError>
     Ok(metadata)
 }
 
-pub async fn tag(path: PathBuf, value: String) -> Result<Cid, TagError> {
+/// Where to read a tag's metadata from
+#[derive(Debug, Clone)]
+pub enum MetadataSource {
+    /// Inline JSON, or `-` for stdin
+    Inline(String),
+    File(PathBuf),
+}
+
+impl MetadataSource {
+    fn read(self) -> Result<String, TagError> {
+        match self {
+            Self::Inline(value) if value == "-" => {
+                let mut value = String::new();
+                std::io::stdin().read_to_string(&mut value)?;
+                Ok(value)
+            }
+            Self::Inline(value) => Ok(value),
+            Self::File(path) => Ok(std::fs::read_to_string(path)?),
+        }
+    }
+}
+
+pub async fn tag(path: PathBuf, source: MetadataSource, merge: bool) -> Result<Cid, TagError> {
+    // Make sure the metadata parses before touching anything
+    let metadata = value_to_metadata(source.read()?)?;
+
+    let _lock = utils::lock_on_disk()?;
     let (mut leaky, change_log) = utils::load_on_disk().await?;
     let mut updates = change_log.clone();
 
     let root_cid = leaky.cid()?;
-    let metadata = value_to_metadata(value)?;
-    leaky.tag(&path, &metadata).await?;
+    let path = utils::bucket_path(&path)?;
+    leaky.tag(&path, &metadata, merge).await?;
     let new_root_cid = leaky.cid()?;
 
     if new_root_cid == root_cid {
@@ -51,8 +76,7 @@ pub async fn tag(path: PathBuf, value: String) -> Result<Cid, TagError> {
         return Ok(root_cid);
     }
 
-    // Get the path stripped of the / prefix
-    let path = clean_path(&path);
+    let path = utils::local_path(&path)?;
     for (c_path, (cid, change)) in change_log.iter() {
         if path == *c_path {
             match change {
@@ -91,3 +115,73 @@ pub enum TagError {
     #[error("device error: {0}")]
     Leaky(#[from] LeakyError),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::utils::test::TestTree;
+    use crate::ops::{add, DiffOptions};
+
+    #[tokio::test]
+    async fn tag_from_file() {
+        let tree = TestTree::init("tag-from-file").await;
+        tree.write("foo", "foo");
+        add(
+            false,
+            false,
+            DiffOptions::default(),
+            DEFAULT_ADD_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+
+        let metadata_path = tree.path().with_file_name("leaky-test-tag-from-file.json");
+        std::fs::write(&metadata_path, r#"{"title": "foo", "rank": 3}"#).unwrap();
+        tag(
+            PathBuf::from("foo"),
+            MetadataSource::File(metadata_path.clone()),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let (leaky, change_log) = utils::load_on_disk().await.unwrap();
+        let object = leaky
+            .get_object(&PathBuf::from("/foo"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            object.metadata().get("title"),
+            Some(&Ipld::String("foo".to_string()))
+        );
+        assert_eq!(object.metadata().get("rank"), Some(&Ipld::Integer(3)));
+        assert!(matches!(
+            change_log.get(&PathBuf::from("foo")),
+            Some((_, ChangeType::Added { modified: true }))
+        ));
+
+        // A file that isn't a JSON object is turned away before anything changes
+        std::fs::write(&metadata_path, "[1, 2]").unwrap();
+        assert!(tag(
+            PathBuf::from("foo"),
+            MetadataSource::File(metadata_path.clone()),
+            false
+        )
+        .await
+        .is_err());
+        let (after, _) = utils::load_on_disk().await.unwrap();
+        assert_eq!(after.cid().unwrap(), leaky.cid().unwrap());
+
+        std::fs::remove_file(&metadata_path).unwrap();
+        assert!(matches!(
+            tag(
+                PathBuf::from("foo"),
+                MetadataSource::File(metadata_path),
+                false
+            )
+            .await,
+            Err(TagError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+
+use super::change_log::ChangeType;
+use super::utils;
+
+/// Bump a file's updated_at, so it goes out with the next push
+pub async fn touch(path: PathBuf) -> Result<Cid, TouchError> {
+    let _lock = utils::lock_on_disk()?;
+    let (mut leaky, change_log) = utils::load_on_disk().await?;
+    let mut updates = change_log.clone();
+
+    let path = utils::bucket_path(&path)?;
+    leaky.touch(&path).await?;
+    let new_root_cid = leaky.cid()?;
+
+    let path = utils::local_path(&path)?;
+    if let Some((cid, change)) = change_log.get(&path) {
+        match change {
+            ChangeType::Added { .. } => {
+                updates.insert(path, (*cid, ChangeType::Added { modified: true }));
+            }
+            ChangeType::Base => {
+                updates.insert(path, (*cid, ChangeType::Modified));
+            }
+            _ => {}
+        }
+    }
+
+    utils::save_on_disk(&mut leaky, &updates).await?;
+
+    Ok(new_root_cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TouchError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("device error: {0}")]
+    Leaky(#[from] LeakyError),
+}
@@ -0,0 +1,21 @@
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// Render the local state as an indented tree, or some other root if `cid` is set.
+///  Rendering another root only reads it, the local state is left alone
+pub async fn tree(cid: Option<Cid>, objects: bool) -> Result<String, TreeError> {
+    let leaky = match cid {
+        Some(cid) => utils::pull_remote(Some(cid)).await?.0,
+        None => utils::load_on_disk().await?.0,
+    };
+    Ok(leaky.render_tree(objects).await?)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TreeError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
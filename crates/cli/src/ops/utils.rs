@@ -1,5 +1,8 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::Result;
 use leaky_common::prelude::*;
@@ -15,6 +18,9 @@ pub const DEFAULT_CONFIG_NAME: &str = "leaky.conf";
 pub const DEFAULT_CACHE_NAME: &str = "leaky.cache";
 pub const DEFAULT_STATE_NAME: &str = "leaky.state";
 pub const DEFAULT_CHAGE_LOG_NAME: &str = "leaky.log";
+pub const DEFAULT_LOCK_NAME: &str = "leaky.lock";
+pub const DEFAULT_PULL_CHECKPOINT_NAME: &str = "leaky.pull";
+pub const DEFAULT_HOOKS_DIR: &str = "hooks";
 
 fn ser_cid(cid: &Cid) -> String {
     format!("cid-{}", cid)
@@ -92,6 +98,22 @@ fn deser_ipld(ipld: &ipld_core::ipld::Ipld) -> Ipld {
 pub struct OnDiskConfig {
     pub ipfs_rpc_url: Url,
     pub leaky_api_url: Url,
+    /// How file data is chunked when it's added
+    #[serde(default)]
+    pub add_options: AddOptions,
+    /// Whether file data is compressed before it's added
+    #[serde(default)]
+    pub compress: bool,
+}
+
+impl OnDiskConfig {
+    /// Build a Leaky from the config, without any state loaded
+    pub fn leaky(&self) -> Result<Leaky> {
+        let mut leaky = Leaky::new(self.ipfs_rpc_url.clone(), self.leaky_api_url.clone())?;
+        leaky.set_add_options(self.add_options);
+        leaky.set_compress(self.compress);
+        Ok(leaky)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +126,8 @@ pub async fn init_on_disk(
     ipfs_rpc_url: Url,
     leaky_api_url: Url,
     cid: Option<Cid>,
+    add_options: AddOptions,
+    compress: bool,
 ) -> Result<Leaky> {
     let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
     let config_path = local_dir_path.join(PathBuf::from(DEFAULT_CONFIG_NAME));
@@ -119,8 +143,15 @@ pub async fn init_on_disk(
         ));
     }
 
+    let on_disk_config = OnDiskConfig {
+        ipfs_rpc_url,
+        leaky_api_url,
+        add_options,
+        compress,
+    };
+
     // Initialize Leaky
-    let mut leaky = Leaky::new(ipfs_rpc_url.clone(), leaky_api_url.clone())?;
+    let mut leaky = on_disk_config.leaky()?;
 
     if let Some(cid) = cid {
         leaky.pull(&cid).await?;
@@ -140,10 +171,6 @@ pub async fn init_on_disk(
         .collect();
 
     // Summarize the state
-    let on_disk_config = OnDiskConfig {
-        ipfs_rpc_url,
-        leaky_api_url,
-    };
     let on_disk_state = OnDiskState { cid, manifest };
 
     // Write everything to disk
@@ -152,14 +179,120 @@ pub async fn init_on_disk(
     std::fs::write(state_path, serde_json::to_string(&on_disk_state)?)?;
     let cache_file = std::fs::File::create(cache_path)?;
     serde_ipld_dagcbor::to_writer(cache_file, &ser_block_cache)?;
-    std::fs::write(change_log_path, serde_json::to_string(&ChangeLog::new())?)?;
+    std::fs::write(change_log_path, ChangeLog::new().to_json()?)?;
 
     Ok(leaky)
 }
 
-pub async fn load_on_disk() -> Result<(Leaky, ChangeLog)> {
+/// Advisory lock over the local state. Held by mutating operations
+///  and released when dropped
+pub struct StateLock(PathBuf);
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+pub fn lock_on_disk() -> Result<StateLock> {
+    let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
+    let lock_path = local_dir_path.join(PathBuf::from(DEFAULT_LOCK_NAME));
+
+    if !local_dir_path.exists() {
+        return Err(anyhow::anyhow!("No leaky directory found"));
+    }
+
+    // Creating the file fails if it already exists, so only one process can hold it
+    match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock_path)
+    {
+        Ok(mut file) => {
+            use std::io::Write;
+            write!(file, "{}", std::process::id())?;
+            Ok(StateLock(lock_path))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(anyhow::anyhow!(
+            "another leaky process is running (remove {:?} if it is not)",
+            lock_path
+        )),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn load_config() -> Result<OnDiskConfig> {
     let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
     let config_path = local_dir_path.join(PathBuf::from(DEFAULT_CONFIG_NAME));
+
+    if !local_dir_path.exists() {
+        return Err(anyhow::anyhow!("No leaky directory found"));
+    }
+
+    let config_str = std::fs::read_to_string(config_path)?;
+    let config: OnDiskConfig = serde_json::from_str(&config_str)?;
+    Ok(config)
+}
+
+/// Progress of a pull that hasn't finished: the root being pulled, and the files
+///  already written for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullCheckpoint {
+    pub cid: Cid,
+    pub pulled: BTreeMap<PathBuf, Cid>,
+}
+
+/// Load the checkpoint left by an interrupted pull of `cid`. Starts over if there
+///  isn't one, or it was for a different root
+pub fn load_pull_checkpoint(cid: &Cid) -> Result<PullCheckpoint> {
+    let checkpoint_path =
+        PathBuf::from(DEFAULT_LOCAL_DIR).join(PathBuf::from(DEFAULT_PULL_CHECKPOINT_NAME));
+    let fresh = PullCheckpoint {
+        cid: *cid,
+        pulled: BTreeMap::new(),
+    };
+    if !checkpoint_path.exists() {
+        return Ok(fresh);
+    }
+    let checkpoint_str = std::fs::read_to_string(checkpoint_path)?;
+    match serde_json::from_str::<PullCheckpoint>(&checkpoint_str) {
+        Ok(checkpoint) if checkpoint.cid == *cid => Ok(checkpoint),
+        _ => Ok(fresh),
+    }
+}
+
+pub fn save_pull_checkpoint(checkpoint: &PullCheckpoint) -> Result<()> {
+    let checkpoint_path =
+        PathBuf::from(DEFAULT_LOCAL_DIR).join(PathBuf::from(DEFAULT_PULL_CHECKPOINT_NAME));
+    std::fs::write(checkpoint_path, serde_json::to_string(checkpoint)?)?;
+    Ok(())
+}
+
+pub fn clear_pull_checkpoint() -> Result<()> {
+    let checkpoint_path =
+        PathBuf::from(DEFAULT_LOCAL_DIR).join(PathBuf::from(DEFAULT_PULL_CHECKPOINT_NAME));
+    if checkpoint_path.exists() {
+        std::fs::remove_file(checkpoint_path)?;
+    }
+    Ok(())
+}
+
+/// Build a fresh Leaky from the on-disk config and pull a root into it, the latest
+///  if none is given. Shares nothing with the local state, so it's safe to read
+///  from alongside it
+pub async fn pull_remote(maybe_cid: Option<Cid>) -> Result<(Leaky, Cid)> {
+    let config = load_config()?;
+    let mut leaky = config.leaky()?;
+    let cid = match maybe_cid {
+        Some(cid) => cid,
+        None => leaky.pull_root_cid().await?,
+    };
+    leaky.pull(&cid).await?;
+    Ok((leaky, cid))
+}
+
+pub async fn load_on_disk() -> Result<(Leaky, ChangeLog)> {
+    let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
     let state_path = local_dir_path.join(PathBuf::from(DEFAULT_STATE_NAME));
     let cache_path = local_dir_path.join(PathBuf::from(DEFAULT_CACHE_NAME));
     let change_log_path = local_dir_path.join(PathBuf::from(DEFAULT_CHAGE_LOG_NAME));
@@ -168,10 +301,7 @@ pub async fn load_on_disk() -> Result<(Leaky, ChangeLog)> {
         return Err(anyhow::anyhow!("No leaky directory found"));
     }
 
-    use std::io::BufReader;
-
-    let config_str = std::fs::read_to_string(config_path)?;
-    let config: OnDiskConfig = serde_json::from_str(&config_str)?;
+    let config = load_config()?;
     let state_str = std::fs::read_to_string(state_path)?;
     let state: OnDiskState = serde_json::from_str(&state_str)?;
     let cache_file = std::fs::File::open(cache_path)?;
@@ -185,7 +315,7 @@ pub async fn load_on_disk() -> Result<(Leaky, ChangeLog)> {
         .collect();
     let block_cache: BlockCache = BlockCache(block_cache);
 
-    let mut leaky = Leaky::new(config.ipfs_rpc_url, config.leaky_api_url)?;
+    let mut leaky = config.leaky()?;
     leaky.load(&state.cid, &state.manifest, block_cache).await?;
 
     // Check if the cid in config matches the cid in the state
@@ -195,7 +325,7 @@ pub async fn load_on_disk() -> Result<(Leaky, ChangeLog)> {
     }
 
     let change_log_str = std::fs::read_to_string(change_log_path)?;
-    let change_log: ChangeLog = serde_json::from_str(&change_log_str)?;
+    let change_log = ChangeLog::from_json(&change_log_str)?;
 
     Ok((leaky, change_log))
 }
@@ -228,12 +358,16 @@ pub async fn save_on_disk(leaky: &mut Leaky, change_log: &ChangeLog) -> Result<(
         .truncate(true)
         .open(cache_path)?;
     serde_ipld_dagcbor::to_writer(cache_file, &ser_block_cache)?;
-    std::fs::write(change_log_path, serde_json::to_string(&change_log)?)?;
+    std::fs::write(change_log_path, change_log.to_json()?)?;
 
     Ok(())
 }
 
-pub fn fs_tree() -> Result<FsTree> {
+/// Read the working tree. Symlinks are skipped unless `follow_symlinks` is set, in which
+///  case each one is replaced by whatever it points to, so the bucket only ever holds
+///  regular files and directories. Broken links, and links back to one of their own
+///  ancestors, are always skipped
+pub fn fs_tree(follow_symlinks: bool) -> Result<FsTree> {
     let dot_dir = PathBuf::from(DEFAULT_LOCAL_DIR);
 
     // Read the Fs-tree at the local directory, ignoring the local directory
@@ -241,12 +375,110 @@ pub fn fs_tree() -> Result<FsTree> {
     match fs_tree::FsTree::read_at(".")? {
         FsTree::Directory(mut d) => {
             let _res = &d.remove_entry(&dot_dir);
-            Ok(fs_tree::FsTree::Directory(d))
+            let mut ancestors = vec![];
+            let tree = resolve_symlinks(
+                FsTree::Directory(d),
+                &PathBuf::from("."),
+                follow_symlinks,
+                &mut ancestors,
+            )?;
+            Ok(tree.unwrap_or(FsTree::Directory(Default::default())))
         }
         _ => Err(anyhow::anyhow!("Expected a directory")),
     }
 }
 
+// Walk a tree, dropping or following the symlinks within it. `ancestors` holds the
+//  canonical paths of the directories we're inside of, to catch loops
+fn resolve_symlinks(
+    tree: FsTree,
+    path: &PathBuf,
+    follow_symlinks: bool,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<Option<FsTree>> {
+    let tree = match tree {
+        FsTree::Symlink(_) if !follow_symlinks => return Ok(None),
+        FsTree::Symlink(_) => {
+            let target = match std::fs::canonicalize(path) {
+                Ok(target) => target,
+                Err(_) => {
+                    pretty_print(format!("skipping broken symlink: {}", path.display()));
+                    return Ok(None);
+                }
+            };
+            if !target.is_dir() {
+                return Ok(Some(FsTree::Regular));
+            }
+            if ancestors.contains(&target) {
+                pretty_print(format!("skipping symlink loop: {}", path.display()));
+                return Ok(None);
+            }
+            FsTree::read_at(&target)?
+        }
+        tree => tree,
+    };
+
+    match tree {
+        FsTree::Directory(entries) => {
+            ancestors.push(std::fs::canonicalize(path)?);
+            let mut resolved = BTreeMap::new();
+            for (name, entry) in entries {
+                let entry_path = path.join(&name);
+                if let Some(entry) =
+                    resolve_symlinks(entry, &entry_path, follow_symlinks, ancestors)?
+                {
+                    resolved.insert(name, entry);
+                }
+            }
+            ancestors.pop();
+            Ok(Some(FsTree::Directory(resolved)))
+        }
+        tree => Ok(Some(tree)),
+    }
+}
+
+pub fn is_empty_dir(path: &PathBuf) -> Result<bool> {
+    if path.as_os_str().is_empty() {
+        return Ok(false);
+    }
+    Ok(std::fs::read_dir(path)?.next().is_none())
+}
+
+/// Where a path given on the command line lives in the bucket. Paths are taken relative
+///  to the root of the bucket, with or without a leading /, and may not climb out of it
+pub fn bucket_path(path: &Path) -> Result<PathBuf> {
+    let mut bucket_path = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(part) => bucket_path.push(part),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "path leaves the bucket: {}",
+                    path.display()
+                ))
+            }
+        }
+    }
+    Ok(bucket_path)
+}
+
+/// Where a path given on the command line lives in the working tree
+pub fn local_path(path: &Path) -> Result<PathBuf> {
+    Ok(bucket_path(path)?.strip_prefix("/")?.to_path_buf())
+}
+
+pub fn pretty_print<T: Display>(value: T) {
+    let bullet = "•";
+    println!("{} {}", bullet, value);
+}
+
+/// Open a file to stream into leaky. Buffered, so it's read a chunk at a time
+///  rather than loaded into memory
+pub fn open_file(path: &PathBuf) -> Result<BufReader<File>> {
+    Ok(BufReader::new(File::open(path)?))
+}
+
 pub async fn hash_file(path: &PathBuf, leaky: &Leaky) -> Result<Cid> {
     if !path.exists() {
         return Err(anyhow::anyhow!("File does not exist"));
@@ -255,9 +487,349 @@ pub async fn hash_file(path: &PathBuf, leaky: &Leaky) -> Result<Cid> {
         return Err(anyhow::anyhow!("Expected a file"));
     }
 
-    let file = std::fs::File::open(path)?;
-
-    let cid = leaky.hash_data(file).await?;
+    let cid = leaky.hash_data(open_file(path)?).await?;
 
     Ok(cid)
 }
+
+#[cfg(test)]
+pub mod test {
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    #[test]
+    fn bucket_paths() {
+        for path in ["foo/bar", "/foo/bar", "./foo/bar", "foo//bar/"] {
+            assert_eq!(
+                bucket_path(Path::new(path)).unwrap(),
+                PathBuf::from("/foo/bar")
+            );
+            assert_eq!(
+                local_path(Path::new(path)).unwrap(),
+                PathBuf::from("foo/bar")
+            );
+        }
+        assert_eq!(local_path(Path::new("/")).unwrap(), PathBuf::from(""));
+        assert!(bucket_path(Path::new("foo/../../bar")).is_err());
+    }
+
+    #[tokio::test]
+    async fn lock_rejects_second_op() {
+        let tree = TestTree::init("lock").await;
+        tree.write("foo", "foo");
+
+        // Held as if by another process partway through an op
+        let lock = lock_on_disk().unwrap();
+        assert!(lock_on_disk().is_err());
+        match crate::ops::add(false, false, Default::default(), DEFAULT_ADD_CONCURRENCY).await {
+            Err(e) => assert!(e.to_string().contains("another leaky process is running")),
+            Ok(_) => panic!("add ran while the state was locked"),
+        }
+        let (_, change_log) = load_on_disk().await.unwrap();
+        assert!(change_log.is_empty());
+
+        // Released on drop
+        drop(lock);
+        assert!(!PathBuf::from(DEFAULT_LOCAL_DIR)
+            .join(DEFAULT_LOCK_NAME)
+            .exists());
+        crate::ops::add(false, false, Default::default(), DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+        let (_, change_log) = load_on_disk().await.unwrap();
+        assert!(change_log.contains_key(&PathBuf::from("foo")));
+    }
+
+    #[tokio::test]
+    async fn pull_remote_matches_state() {
+        let tree = TestTree::init("pull-remote").await;
+        tree.write("foo", "v1");
+        crate::ops::add(false, false, Default::default(), DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+        let old_root = crate::ops::push().await.unwrap();
+        tree.write("foo", "v2");
+        crate::ops::add(false, false, Default::default(), DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+        let root = crate::ops::push().await.unwrap();
+
+        let (remote, cid) = pull_remote(None).await.unwrap();
+        assert_eq!(Some(cid), tree.api.root());
+        assert_eq!(remote.cid().unwrap(), cid);
+        let (leaky, _) = load_on_disk().await.unwrap();
+        assert_eq!(leaky.cid().unwrap(), cid);
+        assert_eq!(cid, root);
+        assert_eq!(remote.items().await.unwrap(), leaky.items().await.unwrap());
+
+        // An older root is pulled as given, without moving the local state
+        let (remote, cid) = pull_remote(Some(old_root)).await.unwrap();
+        assert_eq!(cid, old_root);
+        assert_eq!(remote.cid().unwrap(), old_root);
+        assert_ne!(remote.items().await.unwrap(), leaky.items().await.unwrap());
+        let (leaky, _) = load_on_disk().await.unwrap();
+        assert_eq!(leaky.cid().unwrap(), root);
+    }
+
+    #[tokio::test]
+    async fn push_root_conflicts() {
+        use leaky_common::api::LeakyApi;
+        use leaky_common::error::LeakyApiError;
+
+        let tree = TestTree::init("push-root").await;
+        let previous = tree.api.root().unwrap();
+        tree.write("foo", "foo");
+        crate::ops::add(false, false, Default::default(), DEFAULT_ADD_CONCURRENCY)
+            .await
+            .unwrap();
+        let (leaky, _) = load_on_disk().await.unwrap();
+        let target = leaky.cid().unwrap();
+        let api = LeakyApi::try_from(tree.api.url()).unwrap();
+        let pushes = tree.api.pushes();
+
+        // A retry whose first attempt already landed gets a 409 against its own root
+        tree.api.set_root(&target);
+        tree.api.conflict_next(1);
+        api.push_root(&target, &previous).await.unwrap();
+
+        // Anyone else's root is still a conflict
+        tree.api.set_root(&previous);
+        tree.api.conflict_next(1);
+        assert!(matches!(
+            api.push_root(&target, &previous).await,
+            Err(LeakyApiError::Conflict(_))
+        ));
+        assert_eq!(tree.api.root(), Some(previous));
+        assert_eq!(tree.api.pushes(), pushes);
+    }
+
+    #[tokio::test]
+    async fn symlinks() {
+        use std::collections::BTreeSet;
+        use std::os::unix::fs::symlink;
+
+        let tree = TestTree::empty("symlinks").await;
+        tree.write("file", "file");
+        tree.write("dir/inner", "inner");
+        symlink("file", "file-link").unwrap();
+        symlink("dir", "dir-link").unwrap();
+        symlink("missing", "broken").unwrap();
+        // Points back at the root of the tree, which is an ancestor of the link
+        symlink("..", "dir/up").unwrap();
+
+        let files = |follow_symlinks| {
+            fs_tree(follow_symlinks)
+                .unwrap()
+                .iter()
+                .filter(|(tree, _path)| !tree.is_dir())
+                .map(|(_tree, path)| path)
+                .collect::<BTreeSet<_>>()
+        };
+        let expected = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<BTreeSet<_>>();
+
+        // Skipped by default
+        assert_eq!(files(false), expected(&["dir/inner", "file"]));
+        // Followed when asked, though the broken link and the loop are still skipped
+        assert_eq!(
+            files(true),
+            expected(&["dir-link/inner", "dir/inner", "file", "file-link"])
+        );
+    }
+
+    // Ops work against the current directory, which every test thread shares,
+    //  so tests that need a working tree take turns
+    static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+    /// A fresh working tree, made the current directory until it's dropped
+    pub struct TestTree {
+        pub api: FakeApi,
+        path: PathBuf,
+        previous: PathBuf,
+        _guard: MutexGuard<'static, ()>,
+    }
+
+    impl TestTree {
+        /// An empty working tree, with a bucket initialized and pushed to a fake api
+        pub async fn init(name: &str) -> Self {
+            let tree = Self::empty(name).await;
+            crate::ops::init(
+                Url::parse("http://localhost:5001").unwrap(),
+                tree.api.url(),
+                AddOptions::default(),
+                false,
+            )
+            .await
+            .unwrap();
+            tree
+        }
+
+        /// An empty working tree, without a bucket
+        pub async fn empty(name: &str) -> Self {
+            // A test that panics while holding the lock still leaves the directory usable
+            let guard = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let path =
+                std::env::temp_dir().join(format!("leaky-test-{}-{}", std::process::id(), name));
+            if path.exists() {
+                std::fs::remove_dir_all(&path).unwrap();
+            }
+            std::fs::create_dir_all(&path).unwrap();
+            let previous = std::env::current_dir().unwrap();
+            std::env::set_current_dir(&path).unwrap();
+            Self {
+                api: FakeApi::serve().await,
+                path,
+                previous,
+                _guard: guard,
+            }
+        }
+
+        pub fn path(&self) -> &PathBuf {
+            &self.path
+        }
+
+        /// Write a file into the working tree, creating its parents
+        pub fn write(&self, path: &str, data: &str) {
+            let path = PathBuf::from(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).unwrap();
+            }
+            std::fs::write(path, data).unwrap();
+        }
+
+        pub fn read(&self, path: &str) -> String {
+            std::fs::read_to_string(path).unwrap()
+        }
+    }
+
+    impl Drop for TestTree {
+        fn drop(&mut self) {
+            let _ = std::env::set_current_dir(&self.previous);
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// Stands in for the leaky api, holding the bucket's root in memory
+    #[derive(Clone)]
+    pub struct FakeApi {
+        url: Url,
+        state: Arc<Mutex<FakeApiState>>,
+    }
+
+    #[derive(Default)]
+    struct FakeApiState {
+        root: Option<String>,
+        // How many of the next pushes to turn away, as if someone else pushed first
+        conflicts: usize,
+        pushes: usize,
+    }
+
+    impl FakeApi {
+        pub async fn serve() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+            let api = Self {
+                url,
+                state: Arc::new(Mutex::new(FakeApiState::default())),
+            };
+            let server = api.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let server = server.clone();
+                    tokio::spawn(async move {
+                        let _ = server.respond(stream).await;
+                    });
+                }
+            });
+            api
+        }
+
+        pub fn url(&self) -> Url {
+            self.url.clone()
+        }
+
+        pub fn root(&self) -> Option<Cid> {
+            let state = self.state.lock().unwrap();
+            state.root.as_ref().map(|cid| parse_leaky_cid(cid).unwrap())
+        }
+
+        /// Move the root, as if another client pushed
+        pub fn set_root(&self, cid: &Cid) {
+            self.state.lock().unwrap().root = Some(cid.to_string());
+        }
+
+        /// Reject the next `n` pushes with a 409
+        pub fn conflict_next(&self, n: usize) {
+            self.state.lock().unwrap().conflicts = n;
+        }
+
+        /// How many pushes have been accepted
+        pub fn pushes(&self) -> usize {
+            self.state.lock().unwrap().pushes
+        }
+
+        // Answer a single request, then hang up
+        async fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 4096];
+            let head_len = loop {
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    return Ok(());
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break i + 4;
+                }
+            };
+            let head = String::from_utf8_lossy(&buf[..head_len]).to_string();
+            let content_length = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+                .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            while buf.len() < head_len + content_length {
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            let body = &buf[head_len..(head_len + content_length).min(buf.len())];
+
+            let (status, body) = {
+                let mut state = self.state.lock().unwrap();
+                match head.split_whitespace().next() {
+                    Some("GET") => match &state.root {
+                        Some(root) => ("200 OK", format!("{{\"cid\": \"{}\"}}", root)),
+                        None => ("404 Not Found", String::new()),
+                    },
+                    Some("POST") if state.conflicts > 0 => {
+                        state.conflicts -= 1;
+                        ("409 Conflict", "root has moved".to_string())
+                    }
+                    Some("POST") => {
+                        let request: serde_json::Value =
+                            serde_json::from_slice(body).unwrap_or_default();
+                        state.root = request["cid"].as_str().map(String::from);
+                        state.pushes += 1;
+                        ("200 OK", String::new())
+                    }
+                    _ => ("405 Method Not Allowed", String::new()),
+                }
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await?;
+            stream.shutdown().await
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// How many blocks to check on the remote at once, unless told otherwise
+pub const DEFAULT_VERIFY_CONCURRENCY: usize = 16;
+
+/// Check that the remote has every block reachable from our current root
+pub async fn verify_remote(concurrency: usize) -> Result<Cid, VerifyError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let root_cid = leaky.cid()?;
+    let missing = leaky.missing_blocks(concurrency).await?;
+    if !missing.is_empty() {
+        return Err(VerifyError::MissingBlocks(missing));
+    }
+    Ok(root_cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error(
+        "remote is missing {} blocks:\n{}",
+        .0.len(),
+        .0.iter().map(|cid| cid.to_string()).collect::<Vec<_>>().join("\n")
+    )]
+    MissingBlocks(Vec<Cid>),
+}
@@ -19,9 +19,128 @@ pub use ipfs_api_backend_hyper::IpfsClient;
 
 const DEFAULT_CID_VERSION: u32 = 1;
 const DEFAULT_MH_TYPE: &str = "blake3";
+// UnixFS data type for directories, as reported by `ls`
+const UNIXFS_DIRECTORY_TYPE: u32 = 1;
+/// Size of the chunks `add` splits data into. Anything that fits in one chunk
+///  is stored as a single raw block
+pub const DEFAULT_CHUNK_SIZE: usize = 262144;
+
+/// Compute the cid `add_data` would produce for some bytes, without asking the node.
+///  Only possible when the data fits in a single chunk, otherwise returns None
+pub fn cid_for_bytes(code: MhCode, data: &[u8]) -> Option<Cid> {
+    if data.len() > DEFAULT_CHUNK_SIZE {
+        return None;
+    }
+    Some(raw_cid(code, data))
+}
+
+// The cid of data stored as a single raw block
+pub(crate) fn raw_cid(code: MhCode, data: &[u8]) -> Cid {
+    use libipld::multihash::MultihashDigest;
+    Cid::new_v1(IpldCodec::Raw.into(), code.digest(data))
+}
 
 /* Ipfs Rpc Client Wrapper */
 
+// Pull out the case where the node just doesn't have the block, so callers can
+//  tell that apart from the node being unreachable
+fn map_not_found(cid: &Cid, error: ipfs_api_backend_hyper::Error) -> IpfsRpcError {
+    match &error {
+        ipfs_api_backend_hyper::Error::Api(api_error)
+            if api_error.message.contains("not found") =>
+        {
+            IpfsRpcError::NotFound(*cid)
+        }
+        _ => IpfsRpcError::Client(error),
+    }
+}
+
+/// How `add` splits data into chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Chunker {
+    /// Fixed size chunks of this many bytes
+    Size(usize),
+    /// Content defined chunks, using the node's default rabin parameters
+    Rabin,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self::Size(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl std::fmt::Display for Chunker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Size(size) => write!(f, "size-{}", size),
+            Self::Rabin => write!(f, "rabin"),
+        }
+    }
+}
+
+impl FromStr for Chunker {
+    type Err = IpfsRpcError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "rabin" {
+            return Ok(Self::Rabin);
+        }
+        match s.strip_prefix("size-").map(usize::from_str) {
+            Some(Ok(size)) if size > 0 => Ok(Self::Size(size)),
+            _ => Err(IpfsRpcError::InvalidChunker(s.to_string())),
+        }
+    }
+}
+
+impl From<Chunker> for String {
+    fn from(chunker: Chunker) -> Self {
+        chunker.to_string()
+    }
+}
+
+impl TryFrom<String> for Chunker {
+    type Error = IpfsRpcError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::from_str(&s)
+    }
+}
+
+/// Options for how `add` lays out data. These change the cids data gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddOptions {
+    pub chunker: Chunker,
+    /// Store leaves as raw blocks rather than wrapping them in dag-pb
+    pub raw_leaves: bool,
+}
+
+impl Default for AddOptions {
+    fn default() -> Self {
+        Self {
+            chunker: Chunker::default(),
+            raw_leaves: true,
+        }
+    }
+}
+
+impl AddOptions {
+    /// The most bytes that still end up as a single raw block, if that's predictable
+    pub fn single_block_size(&self) -> Option<usize> {
+        match (self.chunker, self.raw_leaves) {
+            (Chunker::Size(size), true) => Some(size),
+            _ => None,
+        }
+    }
+}
+
+/// A named entry within a UnixFS directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnixFsLink {
+    pub name: String,
+    pub cid: Cid,
+    pub is_dir: bool,
+}
+
 #[derive(Clone)]
 pub struct IpfsRpc<T: Send>(T);
 
@@ -68,18 +187,36 @@ impl IpfsRpc<IpfsClient> {
     // # Returns
     // * the Cid of the data
     pub async fn hash_data<R>(&self, code: MhCode, data: R) -> Result<Cid, IpfsRpcError>
+    where
+        R: Read + Send + Sync + 'static + Unpin,
+    {
+        self.hash_data_with_options(code, &AddOptions::default(), data)
+            .await
+    }
+
+    /// Hash raw data as `add_data_with_options` would lay it out
+    pub async fn hash_data_with_options<R>(
+        &self,
+        code: MhCode,
+        add_options: &AddOptions,
+        data: R,
+    ) -> Result<Cid, IpfsRpcError>
     where
         R: Read + Send + Sync + 'static + Unpin,
     {
         let hash = match code {
             MhCode::Blake3_256 => "blake3",
             MhCode::Sha3_256 => "sha3-256",
+            MhCode::Sha2_256 => "sha2-256",
             _ => DEFAULT_MH_TYPE,
         };
         let mut options = AddRequest::default();
         options.hash = Some(hash);
         options.cid_version = Some(DEFAULT_CID_VERSION);
         options.only_hash = Some(true);
+        let chunker = add_options.chunker.to_string();
+        options.chunker = Some(&chunker);
+        options.raw_leaves = Some(add_options.raw_leaves);
         let response = self.add_with_options(data, options).await?;
         let cid = Cid::from_str(&response.hash)?;
         Ok(cid)
@@ -95,18 +232,36 @@ impl IpfsRpc<IpfsClient> {
     // NOTE: this does not support ALL MhCodes. If an unsupported code is passed, it will use our
     // default of blake3
     pub async fn add_data<R>(&self, code: MhCode, data: R) -> Result<Cid, IpfsRpcError>
+    where
+        R: Read + Send + Sync + 'static + Unpin,
+    {
+        self.add_data_with_options(code, &AddOptions::default(), data)
+            .await
+    }
+
+    /// Add raw data to Ipfs, with control over how it's chunked
+    pub async fn add_data_with_options<R>(
+        &self,
+        code: MhCode,
+        add_options: &AddOptions,
+        data: R,
+    ) -> Result<Cid, IpfsRpcError>
     where
         R: Read + Send + Sync + 'static + Unpin,
     {
         let hash = match code {
             MhCode::Blake3_256 => "blake3",
             MhCode::Sha3_256 => "sha3-256",
+            MhCode::Sha2_256 => "sha2-256",
             _ => DEFAULT_MH_TYPE,
         };
 
         let mut options = AddRequest::default();
         options.hash = Some(hash);
         options.cid_version = Some(DEFAULT_CID_VERSION);
+        let chunker = add_options.chunker.to_string();
+        options.chunker = Some(&chunker);
+        options.raw_leaves = Some(add_options.raw_leaves);
 
         let response = self.add_with_options(data, options).await?;
         let cid = Cid::from_str(&response.hash)?;
@@ -126,7 +281,8 @@ impl IpfsRpc<IpfsClient> {
             .cat(&cid.to_string())
             .map_ok(|chunk| chunk.to_vec())
             .try_concat()
-            .await?;
+            .await
+            .map_err(|e| map_not_found(cid, e))?;
         let response = response_stream;
         Ok(response)
     }
@@ -162,6 +318,7 @@ impl IpfsRpc<IpfsClient> {
         let mhtype = match code {
             MhCode::Blake3_256 => "blake3",
             MhCode::Sha3_256 => "sha3-256",
+            MhCode::Sha2_256 => "sha2-256",
             _ => DEFAULT_MH_TYPE,
         };
 
@@ -181,36 +338,68 @@ impl IpfsRpc<IpfsClient> {
 
     /// Check if the RPC endpoint is pinning the specified CID
     pub async fn has_block(&self, cid: &Cid) -> Result<bool, IpfsRpcError> {
-        let response = self
+        let response = match self
             .pin_ls(Some(&format!("{}", cid.to_string())), None)
-            .await?;
+            .await
+        {
+            Ok(response) => response,
+            // The node errors rather than returning an empty set for unpinned cids
+            Err(ipfs_api_backend_hyper::Error::Api(api_error))
+                if api_error.message.contains("not pinned") =>
+            {
+                return Ok(false);
+            }
+            Err(e) => return Err(e.into()),
+        };
         let keys = response.keys;
         // Check if the cid is pinned
         Ok(keys.contains_key(&cid.to_string()))
     }
 
-    /// Get Block from IPFS
+    /// Get Block from IPFS. The client's response stream isn't Send, so it's read on
+    ///  a blocking thread, leaving the returned future safe to hold across awaits
+    ///  and to spawn
     pub async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, IpfsRpcError> {
-        let stream = self.block_get(&cid.to_string());
-
-        let block_data = stream.map_ok(|chunk| chunk.to_vec()).try_concat().await?;
-        Ok(block_data)
-    }
-
-    pub async fn get_block_send_safe(&self, cid: &Cid) -> Result<Vec<u8>, IpfsRpcError> {
         let cid = *cid;
         let client = self.clone();
-        let response = tokio::task::spawn_blocking(move || {
-            tokio::runtime::Handle::current()
-                .block_on(client.get_block(&cid))
-                .map_err(IpfsRpcError::from)
+        tokio::task::spawn_blocking(move || {
+            tokio::runtime::Handle::current().block_on(client.read_block(&cid))
         })
         .await
         .map_err(|e| {
             IpfsRpcError::Default(anyhow::anyhow!("blockstore tokio runtime error: {e}"))
-        })??;
+        })?
+    }
 
-        Ok(response)
+    // Read a block off of the node. Not Send, see `get_block`
+    async fn read_block(&self, cid: &Cid) -> Result<Vec<u8>, IpfsRpcError> {
+        let stream = self.block_get(&cid.to_string());
+
+        let block_data = stream
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .map_err(|e| map_not_found(cid, e))?;
+        Ok(block_data)
+    }
+
+    /// List the entries of a UnixFS directory on the node
+    pub async fn ls_unixfs(&self, cid: &Cid) -> Result<Vec<UnixFsLink>, IpfsRpcError> {
+        let response = self
+            .ls(&cid.to_string())
+            .await
+            .map_err(|e| map_not_found(cid, e))?;
+        let mut links = vec![];
+        for object in response.objects {
+            for file in object.links {
+                links.push(UnixFsLink {
+                    name: file.name,
+                    cid: Cid::from_str(&file.hash)?,
+                    is_dir: file.typ == UNIXFS_DIRECTORY_TYPE,
+                });
+            }
+        }
+        Ok(links)
     }
 }
 
@@ -228,6 +417,10 @@ pub enum IpfsRpcError {
     Client(#[from] ipfs_api_backend_hyper::Error),
     #[error("cid error")]
     Cid(#[from] wnfs::common::libipld::cid::Error),
+    #[error("block not found: {0}")]
+    NotFound(Cid),
+    #[error("invalid chunker, expected size-<bytes> or rabin: {0}")]
+    InvalidChunker(String),
 }
 
 #[cfg(test)]
@@ -276,6 +469,120 @@ mod tests {
         assert_eq!(cid.hash().code(), 0x1e);
     }
 
+    #[tokio::test]
+    async fn test_cid_for_bytes_matches_hash_data() {
+        use rand::Rng;
+        let ipfs = IpfsRpc::default();
+        let mut rng = rand::thread_rng();
+        for len in [0, 1024, DEFAULT_CHUNK_SIZE] {
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            for mh_code in [MhCode::Blake3_256, MhCode::Sha3_256] {
+                let local = cid_for_bytes(mh_code, &data).unwrap();
+                let remote = ipfs
+                    .hash_data(mh_code, std::io::Cursor::new(data.clone()))
+                    .await
+                    .unwrap();
+                assert_eq!(local, remote);
+            }
+        }
+        let data = vec![0u8; DEFAULT_CHUNK_SIZE + 1];
+        assert!(cid_for_bytes(MhCode::Blake3_256, &data).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_add_data_with_options() {
+        use rand::Rng;
+        let ipfs = IpfsRpc::default();
+        let mut rng = rand::thread_rng();
+        let data: Vec<u8> = (0..4 * DEFAULT_CHUNK_SIZE).map(|_| rng.gen()).collect();
+        let mh_code = MhCode::Blake3_256;
+
+        let mut cids = vec![];
+        for chunker in [Chunker::default(), Chunker::Size(65536), Chunker::Rabin] {
+            let add_options = AddOptions {
+                chunker,
+                raw_leaves: true,
+            };
+            let cid = ipfs
+                .add_data_with_options(mh_code, &add_options, std::io::Cursor::new(data.clone()))
+                .await
+                .unwrap();
+            let hash = ipfs
+                .hash_data_with_options(mh_code, &add_options, std::io::Cursor::new(data.clone()))
+                .await
+                .unwrap();
+            assert_eq!(cid, hash);
+            assert_eq!(ipfs.cat_data(&cid).await.unwrap(), data);
+            cids.push(cid);
+        }
+        assert_ne!(cids[0], cids[1]);
+        assert_ne!(cids[0], cids[2]);
+        // Same data, same options, same cid
+        let cid = ipfs
+            .add_data(mh_code, std::io::Cursor::new(data.clone()))
+            .await
+            .unwrap();
+        assert_eq!(cid, cids[0]);
+
+        // Without raw leaves even a single chunk is wrapped
+        let add_options = AddOptions {
+            chunker: Chunker::default(),
+            raw_leaves: false,
+        };
+        let cid = ipfs
+            .add_data_with_options(mh_code, &add_options, std::io::Cursor::new(b"foo"))
+            .await
+            .unwrap();
+        assert_eq!(IpldCodec::try_from(cid.codec()).unwrap(), IpldCodec::DagPb);
+    }
+
+    #[test]
+    fn test_chunker_from_str() {
+        assert_eq!(Chunker::from_str("rabin").unwrap(), Chunker::Rabin);
+        assert_eq!(Chunker::from_str("size-1024").unwrap(), Chunker::Size(1024));
+        assert_eq!(Chunker::default().to_string(), "size-262144");
+        assert!(Chunker::from_str("size-0").is_err());
+        assert!(Chunker::from_str("buzhash").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_block_not_found() {
+        use libipld::multihash::MultihashDigest;
+        let ipfs = IpfsRpc::default();
+        // Hash some data without ever adding it
+        let mh = MhCode::Blake3_256.digest(b"leaky: this block was never added");
+        let cid = Cid::new_v1(0x55, mh);
+        match ipfs.get_block(&cid).await {
+            Err(IpfsRpcError::NotFound(missing)) => assert_eq!(missing, cid),
+            other => panic!("expected NotFound, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_block_spawned() {
+        use std::io::Read;
+        let ipfs = IpfsRpc::default();
+        let mut data = vec![];
+        random_reader().read_to_end(&mut data).unwrap();
+        let cid = ipfs
+            .put_block(
+                IpldCodec::Raw,
+                MhCode::Blake3_256,
+                std::io::Cursor::new(data.clone()),
+            )
+            .await
+            .unwrap();
+        // Only compiles if get_block's future is Send
+        let block = tokio::spawn(async move {
+            let block = ipfs.get_block(&cid).await.unwrap();
+            tokio::task::yield_now().await;
+            block
+        })
+        .await
+        .unwrap();
+        assert_eq!(block, data);
+    }
+
     #[tokio::test]
     async fn test_put_block_sha3_256_raw() {
         let ipfs = IpfsRpc::default();
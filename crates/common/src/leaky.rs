@@ -1,21 +1,28 @@
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::io::Read;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use futures_util::stream::{self, StreamExt};
+use libipld::codec::Codec;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::ipfs_rpc::{IpfsClient, IpfsRpc, IpfsRpcError};
+use crate::ipfs_rpc::{raw_cid, AddOptions, IpfsClient, IpfsRpc, IpfsRpcError};
 use crate::leaky_api::{LeakyApi, LeakyApiError};
 use crate::types::{
-    Block, Cid, DagCborCodec, DefaultParams, Ipld, IpldCodec, Manifest, MhCode, Node, Object,
+    Block, Cid, CodecProfile, CodecProfileError, DefaultParams, Ipld, IpldCodec, LeakyCid,
+    Manifest, ManifestError, Node, Object, RESERVED_NAMES,
 };
 
+type BoxedReader = Box<dyn Read + Send + Sync + Unpin>;
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct BlockCache(pub HashMap<String, Ipld>);
 
@@ -32,6 +39,27 @@ impl DerefMut for BlockCache {
     }
 }
 
+/// Summary of what a Leaky instance is holding in its block cache
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of node blocks in the cache
+    pub node_count: usize,
+    /// Number of links to raw data across all cached nodes
+    pub data_link_count: usize,
+    /// Size of the cached blocks, encoded as dag-cbor
+    pub cache_bytes: usize,
+}
+
+/// Metadata key recording how a file's data was compressed before it was stored
+pub const COMPRESSION_KEY: &str = "compression";
+const ZSTD_COMPRESSION: &str = "zstd";
+// Only keep compressed data if it saves at least this fraction of the size
+const MIN_COMPRESSION_SAVINGS: f64 = 0.1;
+// How many node blocks to fetch at once when pulling
+const DEFAULT_PULL_CONCURRENCY: usize = 16;
+/// How many items `add_many` hashes or uploads at once, unless told otherwise
+pub const DEFAULT_ADD_CONCURRENCY: usize = 8;
+
 fn cid_string(cid: &Cid) -> String {
     cid.to_string()
 }
@@ -60,8 +88,21 @@ pub struct Leaky {
 
     cid: Option<Cid>,
     manifest: Option<Arc<Mutex<Manifest>>>,
+    // Copy of the manifest's profile, so we don't need to lock the manifest to encode blocks
+    profile: CodecProfile,
+    // How data is chunked when it's added
+    add_options: AddOptions,
+    // Whether to compress data before it's added
+    compress: bool,
+    // How many node blocks to fetch at once when pulling
+    pull_concurrency: usize,
+    // How many items to hash or upload at once when adding many
+    add_concurrency: usize,
     // This should probably be an option
     block_cache: Arc<Mutex<BlockCache>>,
+    // How many blocks have been put to ipfs, so tests can count manifest writes
+    #[cfg(test)]
+    puts: Arc<AtomicUsize>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -98,7 +139,14 @@ impl Leaky {
             leaky_api,
             cid: None,
             manifest: None,
+            profile: CodecProfile::default(),
+            add_options: AddOptions::default(),
+            compress: false,
+            pull_concurrency: DEFAULT_PULL_CONCURRENCY,
+            add_concurrency: DEFAULT_ADD_CONCURRENCY,
             block_cache: Arc::new(Mutex::new(BlockCache::default())),
+            #[cfg(test)]
+            puts: Arc::default(),
         })
     }
 
@@ -109,10 +157,43 @@ impl Leaky {
             ipfs_rpc,
             cid: None,
             manifest: None,
+            profile: CodecProfile::default(),
+            add_options: AddOptions::default(),
+            compress: false,
+            pull_concurrency: DEFAULT_PULL_CONCURRENCY,
+            add_concurrency: DEFAULT_ADD_CONCURRENCY,
             block_cache: Arc::new(Mutex::new(BlockCache::default())),
+            #[cfg(test)]
+            puts: Arc::default(),
         })
     }
 
+    pub fn add_options(&self) -> &AddOptions {
+        &self.add_options
+    }
+
+    /// Set how data is chunked from here on. Data already in the bucket keeps its cids
+    pub fn set_add_options(&mut self, add_options: AddOptions) {
+        self.add_options = add_options;
+    }
+
+    /// Compress data with zstd before it's added. Data that doesn't compress well,
+    ///  like images, is still stored as-is. Compressed data is marked in its
+    ///  object's metadata, and decompressed by `cat`
+    pub fn set_compress(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// Set how many node blocks `pull` fetches at once
+    pub fn set_pull_concurrency(&mut self, pull_concurrency: usize) {
+        self.pull_concurrency = pull_concurrency.max(1);
+    }
+
+    /// Set how many items `add_many` hashes or uploads at once
+    pub fn set_add_concurrency(&mut self, add_concurrency: usize) {
+        self.add_concurrency = add_concurrency.max(1);
+    }
+
     pub fn cid(&self) -> Result<Cid, LeakyError> {
         match self.cid {
             Some(cid) => Ok(cid),
@@ -128,9 +209,34 @@ impl Leaky {
         Ok(self.block_cache.lock().unwrap().to_owned())
     }
 
+    pub fn cache_stats(&self) -> Result<CacheStats, LeakyError> {
+        let block_cache = self.block_cache.lock().unwrap();
+        let mut stats = CacheStats::default();
+        for ipld in block_cache.values() {
+            let block =
+                Block::<DefaultParams>::encode(self.profile.codec()?, self.profile.hash()?, ipld)
+                    .map_err(|_| LeakyError::Ipld)?;
+            stats.cache_bytes += block.data().len();
+            if let Ok(node) = Node::try_from(ipld.clone()) {
+                stats.node_count += 1;
+                stats.data_link_count += node
+                    .get_links()
+                    .values()
+                    .filter(|cid| !cid.is_node_cid())
+                    .count();
+            }
+        }
+        Ok(stats)
+    }
+
     /* Sync functions */
 
     pub async fn init(&mut self) -> Result<(), LeakyError> {
+        self.init_with_profile(CodecProfile::default()).await
+    }
+
+    /// Initialize a new bucket whose blocks are encoded and addressed with the given profile
+    pub async fn init_with_profile(&mut self, profile: CodecProfile) -> Result<(), LeakyError> {
         // Check if we have a cid
         if self.cid.is_some() {
             panic!("already initialized");
@@ -139,13 +245,16 @@ impl Leaky {
             panic!("already initialized");
         }
 
+        self.profile = profile;
+
         // Create a new data node
         let node = Node::default();
         // Put the node into the block_cache
         let cid = self.put_cache::<Node>(&node).await?;
         // Set the data cid in the manifest
         let mut manifest = Manifest::default();
-        manifest.set_data(cid);
+        manifest.set_profile(profile);
+        manifest.try_set_data(cid)?;
 
         let manifest_cid = self.put::<Manifest>(&manifest).await?;
 
@@ -163,6 +272,7 @@ impl Leaky {
         // Set the block cache
         self.block_cache = Arc::new(Mutex::new(block_cache));
         // Set the manifest
+        self.profile = *manifest.profile();
         self.manifest = Some(Arc::new(Mutex::new(manifest.clone())));
         // Set the cid
         self.cid = Some(*cid);
@@ -170,6 +280,32 @@ impl Leaky {
         Ok(())
     }
 
+    /// Load a bucket from a block cache alone, for when ipfs may not be reachable. The
+    ///  manifest is read from the cache if it's there, and only fetched from ipfs on a
+    ///  miss. Reads within the tree never leave the cache
+    pub async fn load_from_cache(
+        &mut self,
+        cid: &Cid,
+        block_cache: BlockCache,
+    ) -> Result<(), LeakyError> {
+        self.block_cache = Arc::new(Mutex::new(block_cache));
+        let manifest = match self.get_cache::<Manifest>(cid).await {
+            Ok(manifest) => manifest,
+            Err(LeakyError::BlockCacheMiss(_)) => self.get::<Manifest>(cid).await?,
+            Err(e) => return Err(e),
+        };
+        if !manifest.is_node_cid(manifest.data()) {
+            return Err(ManifestError::DataNotNode(*manifest.data()).into());
+        }
+        // Catch a cache without the root node now, rather than on the first read
+        self.get_cache::<Node>(manifest.data()).await?;
+
+        self.profile = *manifest.profile();
+        self.manifest = Some(Arc::new(Mutex::new(manifest)));
+        self.cid = Some(*cid);
+        Ok(())
+    }
+
     #[cfg(feature = "leaky-api")]
     pub async fn pull_root_cid(&mut self) -> Result<Cid, LeakyError> {
         let cid = self.leaky_api.pull_root().await?;
@@ -179,6 +315,11 @@ impl Leaky {
     pub async fn pull(&mut self, cid: &Cid) -> Result<(), LeakyError> {
         // Try to pull the manifest from our ipfs_rpc
         let manifest = self.get::<Manifest>(cid).await?;
+        // Catch a bad data link here, rather than failing somewhere down the tree
+        if !manifest.is_node_cid(manifest.data()) {
+            return Err(ManifestError::DataNotNode(*manifest.data()).into());
+        }
+        self.profile = *manifest.profile();
         // Cool! now recurse on the data of the manifest
         // and pull all the links into our local cache
 
@@ -193,10 +334,19 @@ impl Leaky {
     // TODO: pushing should not affect the local state
     #[cfg(feature = "leaky-api")]
     pub async fn push(&mut self) -> Result<(), LeakyError> {
+        // Don't build on a cid that isn't the manifest we're holding
+        let stored_cid = self.cid()?;
+        let computed_cid = self.computed_cid()?;
+        if stored_cid != computed_cid {
+            return Err(LeakyError::CidMismatch(stored_cid, computed_cid));
+        }
+
         // Iterate over the block cache and push all the blocks to ipfs_rpc
         for (cid_str, object) in self.block_cache.lock().unwrap().iter() {
             let cid = self.put::<Ipld>(object).await?;
-            assert_eq!(cid_str, &cid_string(&cid));
+            if cid_str != &cid_string(&cid) {
+                return Err(LeakyError::CorruptCache(vec![cid_str.clone()]));
+            }
         }
 
         let previous_cid = self.cid()?;
@@ -214,11 +364,125 @@ impl Leaky {
         Ok(())
     }
 
+    /// Walk the chain of manifests back from the current root, newest first, stopping
+    ///  after `limit` entries if one is given
+    pub async fn history(&self, limit: Option<usize>) -> Result<Vec<(Cid, Manifest)>, LeakyError> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut history = vec![];
+        let mut cid = self.cid()?;
+        let mut manifest = self.manifest()?;
+        while history.len() < limit {
+            let previous = *manifest.previous();
+            history.push((cid, manifest));
+            // The first manifest in a bucket doesn't have a previous one
+            if previous == Cid::default() {
+                break;
+            }
+            cid = previous;
+            manifest = self.get::<Manifest>(&cid).await?;
+        }
+        Ok(history)
+    }
+
     /* Block management and Pruning */
 
+    /// Compute the cid of the manifest as it's currently held, without touching the network.
+    ///  Should always match `cid`, unless the loaded state was tampered with
+    pub fn computed_cid(&self) -> Result<Cid, LeakyError> {
+        let manifest = self.manifest()?;
+        let block = Block::<DefaultParams>::encode(
+            self.profile.codec()?,
+            self.profile.hash()?,
+            &Into::<Ipld>::into(manifest),
+        )
+        .map_err(|_| LeakyError::Ipld)?;
+        Ok(*block.cid())
+    }
+
+    /// Recompute the cid of every block in the cache and check it matches the key it's
+    ///  stored under. Doesn't touch the network
+    pub fn integrity_check(&self) -> Result<(), LeakyError> {
+        let block_cache = self.block_cache.lock().unwrap();
+        let mut corrupt = vec![];
+        for (cid_str, ipld) in block_cache.iter() {
+            let block =
+                Block::<DefaultParams>::encode(self.profile.codec()?, self.profile.hash()?, ipld)
+                    .map_err(|_| LeakyError::Ipld)?;
+            if cid_str != &cid_string(block.cid()) {
+                corrupt.push(cid_str.clone());
+            }
+        }
+        if !corrupt.is_empty() {
+            corrupt.sort();
+            return Err(LeakyError::CorruptCache(corrupt));
+        }
+        Ok(())
+    }
+
+    /// Check that the remote is pinning every block reachable from the current root,
+    ///  returning any it's missing. Checks up to `concurrency` blocks at a time
+    pub async fn missing_blocks(&self, concurrency: usize) -> Result<Vec<Cid>, LeakyError> {
+        let mut cids = vec![self.cid()?];
+        cids.append(&mut self.reachable_blocks().await?);
+
+        let results = stream::iter(cids)
+            .map(|cid| async move {
+                let has_block = self.ipfs_rpc.has_block(&cid).await?;
+                Ok::<_, LeakyError>((cid, has_block))
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut missing = vec![];
+        for result in results {
+            let (cid, has_block) = result?;
+            if !has_block {
+                missing.push(cid);
+            }
+        }
+        missing.sort();
+        Ok(missing)
+    }
+
+    /// Count how many links point at each block reachable from the data node.
+    ///  The data node itself counts the manifest's link. A shared node is only
+    ///  walked once, so blocks beneath it are counted once per parent block
+    pub async fn ref_counts(&self) -> Result<HashMap<Cid, usize>, LeakyError> {
+        let data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            *manifest.data()
+        };
+        let mut counts = HashMap::new();
+        counts.insert(data_node_cid, 1);
+        let mut queue = vec![data_node_cid];
+        while let Some(cid) = queue.pop() {
+            let node = self.get_cache::<Node>(&cid).await?;
+            for link in node.get_links().values() {
+                let count = counts.entry(*link).or_insert(0);
+                *count += 1;
+                // First time we've seen this node, walk it
+                if *count == 1 && link.is_node_cid() {
+                    queue.push(*link);
+                }
+            }
+        }
+        Ok(counts)
+    }
+
     // Prune the local block cache of un-used blocks
     pub async fn prune(&mut self) -> Result<(), LeakyError> {
-        todo!()
+        let reachable = self
+            .ref_counts()
+            .await?
+            .keys()
+            .map(cid_string)
+            .collect::<HashSet<_>>();
+        self.block_cache
+            .lock()
+            .unwrap()
+            .retain(|cid_str, _ipld| reachable.contains(cid_str));
+        Ok(())
     }
 
     /* Bucket functions */
@@ -235,45 +499,368 @@ impl Leaky {
     {
         let path = clean_path(path);
 
-        let data_cid;
-        if hash_only {
-            data_cid = self.hash_data(data).await?;
+        let (data, compressed) = self.prepare_data(data)?;
+        let data_cid = if hash_only {
+            self.hash_prepared(data).await?
         } else {
-            data_cid = self.add_data(data).await?;
+            self.add_prepared(data).await?
         };
         let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
-        let data_node_cid = manifest.data();
-        let maybe_new_data_node_cid = self
-            .upsert_link_and_object(data_node_cid, &path, Some(&data_cid), maybe_metadata)
+        let data_node_cid = *manifest.data();
+        let mut maybe_new_data_node_cid = self
+            .upsert_link_and_object(&data_node_cid, &path, Some(&data_cid), maybe_metadata)
             .await?;
+        let cid = maybe_new_data_node_cid.unwrap_or(data_node_cid);
+        if let Some(cid) = self.upsert_compression(&cid, &path, compressed).await? {
+            maybe_new_data_node_cid = Some(cid);
+        }
         let new_data_node_cid = match maybe_new_data_node_cid {
             Some(cid) => cid,
             // No Change
             None => return Ok(data_cid),
         };
-        manifest.set_data(new_data_node_cid);
+        manifest.try_set_data(new_data_node_cid)?;
         let manifest_cid = self.put::<Manifest>(&manifest).await?;
         self.cid = Some(manifest_cid);
         Ok(data_cid)
     }
 
+    /// Add many items at once, only recomputing the manifest after all of them
+    ///  are in the tree. Each item is a path, the data, and whether to only hash the data.
+    ///  Data is hashed or uploaded concurrently, but goes into the tree one item at a time
+    pub async fn add_many<R>(
+        &mut self,
+        items: Vec<(PathBuf, R, bool)>,
+    ) -> Result<Vec<Cid>, LeakyError>
+    where
+        R: Read + Send + Sync + 'static + Unpin,
+    {
+        let mut data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            *manifest.data()
+        };
+
+        // Items don't depend on each other until they're upserted, which has to be
+        //  serial since every upsert rewrites nodes along the path to the root
+        let this = &*self;
+        let prepared = stream::iter(items)
+            .map(|(path, data, hash_only)| async move {
+                let (data, compressed) = this.prepare_data(data)?;
+                let data_cid = if hash_only {
+                    this.hash_prepared(data).await?
+                } else {
+                    this.add_prepared(data).await?
+                };
+                Ok::<_, LeakyError>((clean_path(&path), data_cid, compressed))
+            })
+            .buffered(self.add_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut changed = false;
+        let mut data_cids = Vec::with_capacity(prepared.len());
+        for result in prepared {
+            let (path, data_cid, compressed) = result?;
+            if let Some(cid) = self
+                .upsert_link_and_object(&data_node_cid, &path, Some(&data_cid), None)
+                .await?
+            {
+                data_node_cid = cid;
+                changed = true;
+            }
+            if let Some(cid) = self
+                .upsert_compression(&data_node_cid, &path, compressed)
+                .await?
+            {
+                data_node_cid = cid;
+                changed = true;
+            }
+            data_cids.push(data_cid);
+        }
+
+        if changed {
+            let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            manifest.try_set_data(data_node_cid)?;
+            let manifest_cid = self.put::<Manifest>(&manifest).await?;
+            self.cid = Some(manifest_cid);
+        }
+        Ok(data_cids)
+    }
+
+    /// Mirror a UnixFS directory already on the node into the bucket at a path.
+    ///  Files link straight to their existing UnixFS cids, so no data is re-added
+    pub async fn import_unixfs(&mut self, path: &PathBuf, dir_cid: &Cid) -> Result<(), LeakyError> {
+        let mut data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            *manifest.data()
+        };
+
+        let mut changed = false;
+        let mut queue = vec![(clean_path(path), *dir_cid)];
+        while let Some((dir_path, cid)) = queue.pop() {
+            let links = self.ipfs_rpc.ls_unixfs(&cid).await?;
+            // Directories only exist through their children, so empty ones need a node
+            if links.is_empty() && dir_path.iter().count() > 0 {
+                let empty_cid = self.put_cache::<Node>(&Node::default()).await?;
+                if let Some(cid) = self
+                    .upsert_link_and_object(&data_node_cid, &dir_path, Some(&empty_cid), None)
+                    .await?
+                {
+                    data_node_cid = cid;
+                    changed = true;
+                }
+            }
+            for link in links {
+                let link_path = dir_path.join(&link.name);
+                if RESERVED_NAMES.contains(&link.name.as_str()) {
+                    return Err(LeakyError::ReservedName(link_path));
+                }
+                if link.is_dir {
+                    queue.push((link_path, link.cid));
+                    continue;
+                }
+                if let Some(cid) = self
+                    .upsert_link_and_object(&data_node_cid, &link_path, Some(&link.cid), None)
+                    .await?
+                {
+                    data_node_cid = cid;
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            manifest.try_set_data(data_node_cid)?;
+            let manifest_cid = self.put::<Manifest>(&manifest).await?;
+            self.cid = Some(manifest_cid);
+        }
+        Ok(())
+    }
+
+    /// Graft an existing node, and everything beneath it, into the bucket as a directory
+    ///  at a path, replacing whatever is there. The node's blocks must be on the node already
+    pub async fn graft(&mut self, path: &PathBuf, node_cid: &Cid) -> Result<(), LeakyError> {
+        if !node_cid.is_node_cid() {
+            return Err(LeakyError::NotNode(*node_cid));
+        }
+        let path = clean_path(path);
+        if path.iter().count() == 0 {
+            return Err(LeakyError::PathNotDir(path));
+        }
+        // Make sure this is actually a node, and bring the subtree into the cache
+        self.pull_links(node_cid).await?;
+
+        let mut data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            *manifest.data()
+        };
+        // Clear out the path first, otherwise an existing directory is left in place
+        if let Some(cid) = self
+            .upsert_link_and_object(&data_node_cid, &path, None, None)
+            .await?
+        {
+            data_node_cid = if cid == Cid::default() {
+                self.put_cache::<Node>(&Node::default()).await?
+            } else {
+                cid
+            };
+        }
+        if let Some(cid) = self
+            .upsert_link_and_object(&data_node_cid, &path, Some(node_cid), None)
+            .await?
+        {
+            data_node_cid = cid;
+        }
+
+        let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        manifest.try_set_data(data_node_cid)?;
+        let manifest_cid = self.put::<Manifest>(&manifest).await?;
+        self.cid = Some(manifest_cid);
+        Ok(())
+    }
+
+    /// Exchange whatever is at two paths, keeping each file's metadata with its data.
+    ///  Both paths must exist, and neither may be beneath the other
+    pub async fn swap(&mut self, a: &PathBuf, b: &PathBuf) -> Result<(), LeakyError> {
+        let a_path = clean_path(a);
+        let b_path = clean_path(b);
+        if a_path.starts_with(&b_path) || b_path.starts_with(&a_path) {
+            return Err(LeakyError::OverlappingPaths(a.clone(), b.clone()));
+        }
+        let (a_link, a_object) = self.entry_at(a).await?;
+        let (b_link, b_object) = self.entry_at(b).await?;
+
+        let mut data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            *manifest.data()
+        };
+        for path in [&a_path, &b_path] {
+            if let Some(cid) = self
+                .upsert_link_and_object(&data_node_cid, path, None, None)
+                .await?
+            {
+                data_node_cid = if cid == Cid::default() {
+                    self.put_cache::<Node>(&Node::default()).await?
+                } else {
+                    cid
+                };
+            }
+        }
+        for (path, link, object) in [(&a_path, b_link, b_object), (&b_path, a_link, a_object)] {
+            let metadata = object.map(|object| object.metadata().clone());
+            if let Some(cid) = self
+                .upsert_link_and_object(&data_node_cid, path, Some(&link), metadata.as_ref())
+                .await?
+            {
+                data_node_cid = cid;
+            }
+        }
+
+        let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        manifest.try_set_data(data_node_cid)?;
+        let manifest_cid = self.put::<Manifest>(&manifest).await?;
+        self.cid = Some(manifest_cid);
+        Ok(())
+    }
+
+    // Get the link, and object if it's a file, at a path
+    async fn entry_at(&self, path: &PathBuf) -> Result<(Cid, Option<Object>), LeakyError> {
+        let clean = clean_path(path);
+        let name = match clean.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err(LeakyError::PathDoesNotExist(path.clone())),
+        };
+        let parent = PathBuf::from("/").join(clean.parent().unwrap());
+        let links = match self.ls(&parent).await {
+            Err(LeakyError::PathDoesNotExist(_)) | Err(LeakyError::PathNotDir(_)) => {
+                return Err(LeakyError::PathDoesNotExist(path.clone()))
+            }
+            result => result?,
+        };
+        links
+            .into_iter()
+            .find(|(n, _)| n == &name)
+            .map(|(_name, entry)| entry)
+            .ok_or_else(|| LeakyError::PathDoesNotExist(path.clone()))
+    }
+
+    /// Copy a file or directory out of another bucket, without moving any data.
+    ///  Files keep their metadata, directories are grafted whole
+    pub async fn copy_from(
+        &mut self,
+        source: &Leaky,
+        from: &PathBuf,
+        to: &PathBuf,
+    ) -> Result<(), LeakyError> {
+        let from_path = clean_path(from);
+        let link = match from_path.file_name() {
+            // The root of the source bucket
+            None => *source.manifest()?.data(),
+            Some(name) => {
+                let parent = PathBuf::from("/").join(from_path.parent().unwrap());
+                let name = name.to_string_lossy().to_string();
+                match source
+                    .ls(&parent)
+                    .await?
+                    .into_iter()
+                    .find(|(n, _)| n == &name)
+                {
+                    Some((_name, (link, _object))) => link,
+                    None => return Err(LeakyError::PathDoesNotExist(from.clone())),
+                }
+            }
+        };
+
+        if link.is_node_cid() {
+            return self.graft(to, &link).await;
+        }
+
+        let metadata = source
+            .get_object(from)
+            .await?
+            .map(|object| object.metadata().clone());
+        let to_path = clean_path(to);
+        let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        let data_node_cid = manifest.data();
+        let maybe_new_data_node_cid = self
+            .upsert_link_and_object(data_node_cid, &to_path, Some(&link), metadata.as_ref())
+            .await?;
+        let new_data_node_cid = match maybe_new_data_node_cid {
+            Some(cid) => cid,
+            // No Change
+            None => return Ok(()),
+        };
+        manifest.try_set_data(new_data_node_cid)?;
+        let manifest_cid = self.put::<Manifest>(&manifest).await?;
+        self.cid = Some(manifest_cid);
+        Ok(())
+    }
+
+    /// Tag the object at a path with metadata. If `merge` is set, the provided
+    ///  metadata is overlayed onto the existing metadata, otherwise it replaces it
     pub async fn tag(
         &mut self,
         path: &PathBuf,
         metadata: &BTreeMap<String, Ipld>,
+        merge: bool,
     ) -> Result<(), LeakyError> {
+        // Objects only hang off of data links, so the file must already be added
+        let object = match self.get_object(path).await? {
+            Some(object) => object,
+            None => return Err(LeakyError::PathDoesNotExist(path.clone())),
+        };
+        let metadata = if merge {
+            let mut merged = object.metadata().clone();
+            merged.extend(metadata.clone());
+            merged
+        } else {
+            // Replacing the metadata mustn't lose track of how the data is stored
+            let mut replaced = metadata.clone();
+            if let Some(compression) = object.metadata().get(COMPRESSION_KEY) {
+                replaced.insert(COMPRESSION_KEY.to_string(), compression.clone());
+            }
+            replaced
+        };
+        let path = clean_path(path);
+        let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        let data_node_cid = manifest.data();
+        let maybe_new_data_node_cid = self
+            .upsert_link_and_object(data_node_cid, &path, None, Some(&metadata))
+            .await?;
+        let new_data_node_cid = match maybe_new_data_node_cid {
+            Some(cid) => cid,
+            // No Change
+            None => return Ok(()),
+        };
+        manifest.try_set_data(new_data_node_cid)?;
+        let manifest_cid = self.put::<Manifest>(&manifest).await?;
+        self.cid = Some(manifest_cid);
+        Ok(())
+    }
+
+    /// Bump the updated_at of the object at a path, leaving its data and metadata alone.
+    ///  Files without an object are given an empty one
+    pub async fn touch(&mut self, path: &PathBuf) -> Result<(), LeakyError> {
+        let (link, object) = self.entry_at(path).await?;
+        if link.is_node_cid() {
+            return Err(LeakyError::PathNotFile(path.clone()));
+        }
+        let metadata = object
+            .map(|object| object.metadata().clone())
+            .unwrap_or_default();
         let path = clean_path(path);
         let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
         let data_node_cid = manifest.data();
         let maybe_new_data_node_cid = self
-            .upsert_link_and_object(data_node_cid, &path, None, Some(metadata))
+            .upsert_link_and_object(data_node_cid, &path, None, Some(&metadata))
             .await?;
         let new_data_node_cid = match maybe_new_data_node_cid {
             Some(cid) => cid,
             // No Change
             None => return Ok(()),
         };
-        manifest.set_data(new_data_node_cid);
+        manifest.try_set_data(new_data_node_cid)?;
         let manifest_cid = self.put::<Manifest>(&manifest).await?;
         self.cid = Some(manifest_cid);
         Ok(())
@@ -299,7 +886,48 @@ impl Leaky {
             // No Change
             None => return Ok(()),
         };
-        manifest.set_data(new_data_node_cid);
+        manifest.try_set_data(new_data_node_cid)?;
+        let manifest_cid = self.put::<Manifest>(&manifest).await?;
+        self.cid = Some(manifest_cid);
+        Ok(())
+    }
+
+    /// Create an empty directory at a path. Does nothing if there's already a directory
+    ///  there, and fails if there's a file
+    pub async fn mkdir(&mut self, path: &PathBuf) -> Result<(), LeakyError> {
+        if self.ls(path).await.is_ok() {
+            return Ok(());
+        }
+        let path = clean_path(path);
+        let dir_cid = self.put_cache::<Node>(&Node::default()).await?;
+        let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        let data_node_cid = manifest.data();
+        let maybe_new_data_node_cid = self
+            .upsert_link_and_object(data_node_cid, &path, Some(&dir_cid), None)
+            .await?;
+        let new_data_node_cid = match maybe_new_data_node_cid {
+            Some(cid) => cid,
+            // No Change
+            None => return Ok(()),
+        };
+        manifest.try_set_data(new_data_node_cid)?;
+        let manifest_cid = self.put::<Manifest>(&manifest).await?;
+        self.cid = Some(manifest_cid);
+        Ok(())
+    }
+
+    /// Set (or clear) the metadata applied to files added to a directory without their own
+    pub async fn set_default(
+        &mut self,
+        path: &PathBuf,
+        maybe_metadata: Option<&BTreeMap<String, Ipld>>,
+    ) -> Result<(), LeakyError> {
+        let path = clean_path(path);
+        let new_data_node_cid = self
+            .update_dir(&path, |node| node.set_default(maybe_metadata))
+            .await?;
+        let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        manifest.try_set_data(new_data_node_cid)?;
         let manifest_cid = self.put::<Manifest>(&manifest).await?;
         self.cid = Some(manifest_cid);
         Ok(())
@@ -320,7 +948,10 @@ impl Leaky {
         // Iterate on the remaining path
         for part in path.iter() {
             let next = part.to_string_lossy().to_string();
-            let next_cid = node.get_link(&next).unwrap();
+            let next_cid = match node.get_link(&next) {
+                Some(cid) => cid,
+                None => return Err(LeakyError::PathDoesNotExist(path)),
+            };
             node = match self.get_cache::<Node>(&next_cid).await {
                 Ok(node) => node,
                 Err(_) => {
@@ -350,15 +981,31 @@ impl Leaky {
         Ok(sorted_items)
     }
 
-    pub async fn cat(&self, path: &PathBuf) -> Result<Vec<u8>, LeakyError> {
+    /// Return all the empty directories in the bucket in order by path name
+    pub async fn empty_dirs(&self) -> Result<Vec<PathBuf>, LeakyError> {
+        let mut dirs = self.recursive_empty_dirs(&PathBuf::from("/")).await?;
+        dirs.sort();
+        Ok(dirs)
+    }
+
+    /// Get the object attached to a path, if one exists
+    pub async fn get_object(&self, path: &PathBuf) -> Result<Option<Object>, LeakyError> {
         let path = clean_path(path);
         let data_node_cid = {
             let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
             let mc = manifest.clone();
             *mc.data()
         };
-        let node = self.get_cache::<Node>(&data_node_cid).await?;
-        let mut node = node;
+        self.object_at(&data_node_cid, &path).await
+    }
+
+    // Get the object at a (clean) path beneath a data node
+    async fn object_at(
+        &self,
+        data_node_cid: &Cid,
+        path: &Path,
+    ) -> Result<Option<Object>, LeakyError> {
+        let mut node = self.get_cache::<Node>(data_node_cid).await?;
         // Get the dir path
         let dir_path = path
             .iter()
@@ -370,37 +1017,182 @@ impl Leaky {
         // Iterate on the remaining path
         for part in dir_path.iter() {
             let next = part.to_string_lossy().to_string();
-            let next_cid = node.get_link(&next).unwrap();
+            let next_cid = match node.get_link(&next) {
+                Some(cid) => cid,
+                None => return Ok(None),
+            };
             node = self.get_cache::<Node>(&next_cid).await?;
         }
 
-        // Get the link from the node
-        let link = node.get_link(&file_name).unwrap();
-        let data = self.cat_data(&link).await?;
+        Ok(node.get_object(&file_name))
+    }
 
-        Ok(data)
+    /// Get the decoded contents of a node or manifest block, preferring what's
+    ///  in the block cache over asking the ipfs node
+    pub async fn inspect(&self, cid: &Cid) -> Result<Ipld, LeakyError> {
+        if !cid.is_node_cid() {
+            return Err(LeakyError::NotNode(*cid));
+        }
+        let cached = self
+            .block_cache
+            .lock()
+            .unwrap()
+            .get(&cid_string(cid))
+            .cloned();
+        match cached {
+            Some(ipld) => Ok(ipld),
+            None => self.get::<Ipld>(cid).await,
+        }
     }
 
-    /* Helper functions */
+    /// Render a node or manifest block as pretty printed dag-json,
+    ///  with links written as `{"/": "<cid>"}`
+    pub async fn inspect_json(&self, cid: &Cid) -> Result<String, LeakyError> {
+        let ipld = self.inspect(cid).await?;
+        let data = IpldCodec::DagJson
+            .encode(&ipld)
+            .map_err(|_| LeakyError::Ipld)?;
+        let value: serde_json::Value = serde_json::from_slice(&data)?;
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
 
-    /// Recursively bubble up all the items from a path
-    ///  in sorted order
-    #[async_recursion::async_recursion]
-    async fn recursive_items(&self, path: &PathBuf) -> Result<Vec<(PathBuf, Cid)>, LeakyError> {
-        let mut items = vec![];
-        let links = match self.ls(path).await {
-            Ok(l) => l,
-            Err(err) => match err {
-                LeakyError::PathNotDir(_) => {
-                    return Ok(items);
+    /// Render the bucket as an indented tree, one line per directory or file with
+    ///  its kind and cid. With `with_objects`, files also show when they were last
+    ///  updated and what metadata keys they carry
+    pub async fn render_tree(&self, with_objects: bool) -> Result<String, LeakyError> {
+        let data_node_cid = *self.manifest.as_ref().unwrap().lock().unwrap().data();
+        let mut lines = vec![format!("/ [dir] {}", data_node_cid)];
+        // Children are pushed in reverse, so they pop back off in order
+        let mut stack = self
+            .ls(&PathBuf::from("/"))
+            .await?
+            .into_iter()
+            .rev()
+            .map(|entry| (PathBuf::from("/"), 1, entry))
+            .collect::<Vec<_>>();
+        while let Some((dir, depth, (name, (cid, object)))) = stack.pop() {
+            let indent = "  ".repeat(depth);
+            let line = match object {
+                // Files always carry an object, directories never do
+                Some(object) => {
+                    let mut line = format!("{}{} [file] {}", indent, name, cid);
+                    if with_objects {
+                        line.push_str(&format!(" (updated {}", object.updated_at()));
+                        if !object.metadata().is_empty() {
+                            let keys = object.metadata().keys().cloned().collect::<Vec<_>>();
+                            line.push_str(&format!(", metadata: {}", keys.join(", ")));
+                        }
+                        line.push(')');
+                    }
+                    line
                 }
-                _ => return Err(err),
-            },
-        };
-        for (name, (_link, object)) in links {
-            // If this is a directory, recurse
-            if object.is_none() {
-                let mut path = path.clone();
+                None => {
+                    let path = dir.join(&name);
+                    for entry in self.ls(&path).await?.into_iter().rev() {
+                        stack.push((path.clone(), depth + 1, entry));
+                    }
+                    format!("{}{}/ [dir] {}", indent, name, cid)
+                }
+            };
+            lines.push(line);
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Read the data of the file at a path. Directories have no data to read
+    pub async fn cat(&self, path: &PathBuf) -> Result<Vec<u8>, LeakyError> {
+        let original_path = path;
+        let path = clean_path(path);
+        if path.iter().count() == 0 {
+            return Err(LeakyError::PathNotFile(original_path.clone()));
+        }
+        let data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            let mc = manifest.clone();
+            *mc.data()
+        };
+        let node = self.get_cache::<Node>(&data_node_cid).await?;
+        let mut node = node;
+        // Get the dir path
+        let dir_path = path
+            .iter()
+            .take(path.iter().count() - 1)
+            .collect::<PathBuf>();
+        // Get the file name
+        let file_name = path.iter().last().unwrap().to_string_lossy().to_string();
+
+        // Iterate on the remaining path
+        for part in dir_path.iter() {
+            let next = part.to_string_lossy().to_string();
+            let next_cid = match node.get_link(&next) {
+                Some(cid) if cid.is_node_cid() => cid,
+                _ => return Err(LeakyError::PathDoesNotExist(original_path.clone())),
+            };
+            node = self.get_cache::<Node>(&next_cid).await?;
+        }
+
+        // Get the link from the node
+        let link = match node.get_link(&file_name) {
+            Some(link) if link.is_node_cid() => {
+                return Err(LeakyError::PathNotFile(original_path.clone()))
+            }
+            Some(link) => link,
+            None => return Err(LeakyError::PathDoesNotExist(original_path.clone())),
+        };
+        let data = self.cat_data(&link).await?;
+
+        let compression = node
+            .get_object(&file_name)
+            .and_then(|object| object.metadata().get(COMPRESSION_KEY).cloned());
+        match compression {
+            None => Ok(data),
+            Some(Ipld::String(c)) if c == ZSTD_COMPRESSION => Ok(zstd::decode_all(&data[..])?),
+            Some(c) => Err(LeakyError::UnsupportedCompression(format!("{:?}", c))),
+        }
+    }
+
+    /* Helper functions */
+
+    /// All the node and data cids reachable from the current data node
+    async fn reachable_blocks(&self) -> Result<Vec<Cid>, LeakyError> {
+        let data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            *manifest.data()
+        };
+        let mut cids = vec![];
+        let mut queue = vec![data_node_cid];
+        while let Some(cid) = queue.pop() {
+            cids.push(cid);
+            let node = self.get_cache::<Node>(&cid).await?;
+            for link in node.get_links().values() {
+                if link.is_node_cid() {
+                    queue.push(*link);
+                } else {
+                    cids.push(*link);
+                }
+            }
+        }
+        Ok(cids)
+    }
+
+    /// Recursively bubble up all the items from a path
+    ///  in sorted order
+    #[async_recursion::async_recursion]
+    async fn recursive_items(&self, path: &PathBuf) -> Result<Vec<(PathBuf, Cid)>, LeakyError> {
+        let mut items = vec![];
+        let links = match self.ls(path).await {
+            Ok(l) => l,
+            Err(err) => match err {
+                LeakyError::PathNotDir(_) => {
+                    return Ok(items);
+                }
+                _ => return Err(err),
+            },
+        };
+        for (name, (_link, object)) in links {
+            // If this is a directory, recurse
+            if object.is_none() {
+                let mut path = path.clone();
                 path.push(name);
                 let mut next_items = self.recursive_items(&path).await?;
                 items.append(&mut next_items);
@@ -413,30 +1205,104 @@ impl Leaky {
         Ok(items)
     }
 
+    /// Recursively bubble up all the empty directories beneath a path
     #[async_recursion::async_recursion]
-    async fn pull_links(&mut self, cid: &Cid) -> Result<(), LeakyError> {
-        let node = self.get::<Node>(cid).await?;
-        self.block_cache
-            .lock()
-            .unwrap()
-            .insert(cid_string(cid), node.clone().into());
-        // Recurse from down the data node, pulling all the nodes
-        for (_name, link) in node.clone().iter() {
-            match link {
-                Ipld::Link(cid) => {
-                    // Check if this is raw data
-                    if cid.codec() == 0x55 {
-                        return Ok(());
-                    };
-                    self.pull_links(cid).await?;
+    async fn recursive_empty_dirs(&self, path: &PathBuf) -> Result<Vec<PathBuf>, LeakyError> {
+        let mut dirs = vec![];
+        let links = self.ls(path).await?;
+        for (name, (_link, object)) in links {
+            // Only directories are missing objects
+            if object.is_some() {
+                continue;
+            }
+            let mut path = path.clone();
+            path.push(name);
+            if self.ls(&path).await?.is_empty() {
+                dirs.push(path);
+            } else {
+                let mut next_dirs = self.recursive_empty_dirs(&path).await?;
+                dirs.append(&mut next_dirs);
+            }
+        }
+        Ok(dirs)
+    }
+
+    // Pull a node and every node beneath it into the cache. The tree is walked a
+    //  level at a time, since a node's children aren't known until it's fetched,
+    //  with up to `pull_concurrency` nodes in a level fetched at once
+    async fn pull_links(&self, cid: &Cid) -> Result<(), LeakyError> {
+        let mut seen = HashSet::from([*cid]);
+        let mut level = vec![*cid];
+        while !level.is_empty() {
+            let results = stream::iter(level)
+                .map(|cid| async move {
+                    let node = self.get::<Node>(&cid).await?;
+                    Ok::<_, LeakyError>((cid, node))
+                })
+                .buffer_unordered(self.pull_concurrency)
+                .collect::<Vec<_>>()
+                .await;
+
+            let mut next = vec![];
+            for result in results {
+                let (cid, node) = result?;
+                for link in node.values() {
+                    // Data isn't held in the cache, and shared nodes only need pulling once
+                    if let Ipld::Link(link) = link {
+                        if link.is_node_cid() && seen.insert(*link) {
+                            next.push(*link);
+                        }
+                    }
                 }
-                // Just ignore anything that's not a link
-                _ => {}
+                self.block_cache
+                    .lock()
+                    .unwrap()
+                    .insert(cid_string(&cid), node.into());
             }
+            level = next;
         }
         Ok(())
     }
 
+    /// Apply an update to the node at a directory path, percolating the new
+    ///  cids back up to the root. Returns the cid of the new data node
+    async fn update_dir<F>(&self, path: &Path, f: F) -> Result<Cid, LeakyError>
+    where
+        F: FnOnce(&mut Node),
+    {
+        let data_node_cid = {
+            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+            *manifest.data()
+        };
+        let mut node = self.get_cache::<Node>(&data_node_cid).await?;
+
+        // Walk down to the directory, holding onto each parent along the way
+        let mut parents = vec![];
+        for part in path.iter() {
+            let next = part.to_string_lossy().to_string();
+            let next_cid = match node.get_link(&next) {
+                Some(cid) => cid,
+                None => return Err(LeakyError::PathDoesNotExist(path.to_path_buf())),
+            };
+            let next_node = match self.get_cache::<Node>(&next_cid).await {
+                Ok(node) => node,
+                Err(_) => return Err(LeakyError::PathNotDir(path.to_path_buf())),
+            };
+            parents.push((next, node));
+            node = next_node;
+        }
+
+        f(&mut node);
+
+        // And back up again
+        let mut cid = self.put_cache::<Node>(&node).await?;
+        while let Some((name, mut parent)) = parents.pop() {
+            parent.put_link(&name, &cid);
+            cid = self.put_cache::<Node>(&parent).await?;
+        }
+        Ok(cid)
+    }
+
     // TODO: this doesn't percolate deleted directories back up
     #[async_recursion::async_recursion]
     async fn upsert_link_and_object(
@@ -473,8 +1339,22 @@ impl Leaky {
                     if node.size() == 0 {
                         return Ok(Some(Cid::default()));
                     }
+                } else if maybe_metadata.is_none()
+                    && maybe_link.map_or(false, |cid| cid.is_node_cid())
+                {
+                    // Links to nodes are directories, and don't carry objects.
+                    //  Callers clear the path first if they mean to replace what's there
+                    if node.get_link(&next).is_some() {
+                        return Err(LeakyError::PathExists(PathBuf::from(next)));
+                    }
+                    node.put_link(&next, maybe_link.unwrap());
                 } else {
-                    node.update_link(&next, maybe_link, maybe_metadata);
+                    // Stamp the directory's default onto new links that weren't given metadata
+                    let default = match maybe_metadata {
+                        None if node.get_object(&next).is_none() => node.get_default(),
+                        _ => None,
+                    };
+                    node.update_link(&next, maybe_link, maybe_metadata.or(default.as_ref()));
                 }
 
                 // The node is updated, put it back into the cache and return the new cid
@@ -500,7 +1380,14 @@ impl Leaky {
                 // Upsert the remaining path components into the node
                 let maybe_cid = &self
                     .upsert_link_and_object(&next_cid, &remaining, maybe_link, maybe_metadata)
-                    .await?;
+                    .await
+                    .map_err(|e| match e {
+                        // Report the whole path, not just the part beneath this node
+                        LeakyError::PathExists(p) => {
+                            LeakyError::PathExists(Path::new(&next).join(p))
+                        }
+                        e => e,
+                    })?;
                 let cid = match maybe_cid {
                     Some(cid) => cid,
                     // No change, return the original cid
@@ -523,19 +1410,93 @@ impl Leaky {
 
     /* Data operations */
 
+    /// Record whether the data at a (clean) path was compressed in its object's metadata.
+    ///  Returns the new data node cid, if anything changed
+    async fn upsert_compression(
+        &self,
+        data_node_cid: &Cid,
+        path: &Path,
+        compressed: bool,
+    ) -> Result<Option<Cid>, LeakyError> {
+        let mut metadata = match self.object_at(data_node_cid, path).await? {
+            Some(object) => object.metadata().clone(),
+            None => return Ok(None),
+        };
+        let changed = if compressed {
+            let compression = Ipld::String(ZSTD_COMPRESSION.to_string());
+            metadata.insert(COMPRESSION_KEY.to_string(), compression.clone()) != Some(compression)
+        } else {
+            metadata.remove(COMPRESSION_KEY).is_some()
+        };
+        if !changed {
+            return Ok(None);
+        }
+        self.upsert_link_and_object(data_node_cid, path, None, Some(&metadata))
+            .await
+    }
+
+    // Compress data if that's turned on and worth it. Returns the data to store,
+    //  and whether it was compressed
+    fn prepare_data<R>(&self, data: R) -> Result<(BoxedReader, bool), LeakyError>
+    where
+        R: Read + Send + Sync + 'static + Unpin,
+    {
+        if !self.compress {
+            return Ok((Box::new(data), false));
+        }
+        let mut data = data;
+        let mut raw = Vec::new();
+        data.read_to_end(&mut raw)?;
+        let compressed = zstd::encode_all(&raw[..], 0)?;
+        if (compressed.len() as f64) <= raw.len() as f64 * (1.0 - MIN_COMPRESSION_SAVINGS) {
+            Ok((Box::new(std::io::Cursor::new(compressed)), true))
+        } else {
+            Ok((Box::new(std::io::Cursor::new(raw)), false))
+        }
+    }
+
+    /// Compute the cid `add_data` would give some data. Data that fits in a single
+    ///  raw block is hashed locally, anything else needs a trip to the node
     pub async fn hash_data<R>(&self, data: R) -> Result<Cid, LeakyError>
     where
         R: Read + Send + Sync + 'static + Unpin,
     {
-        let cid = self.ipfs_rpc.hash_data(MhCode::Blake3_256, data).await?;
-        Ok(cid)
+        let (data, _compressed) = self.prepare_data(data)?;
+        self.hash_prepared(data).await
     }
 
     pub async fn add_data<R>(&self, data: R) -> Result<Cid, LeakyError>
     where
         R: Read + Send + Sync + 'static + Unpin,
     {
-        let cid = self.ipfs_rpc.add_data(MhCode::Blake3_256, data).await?;
+        let (data, _compressed) = self.prepare_data(data)?;
+        self.add_prepared(data).await
+    }
+
+    async fn hash_prepared(&self, mut data: BoxedReader) -> Result<Cid, LeakyError> {
+        let data: BoxedReader = match self.add_options.single_block_size() {
+            Some(size) => {
+                let mut head = Vec::new();
+                (&mut data).take(size as u64 + 1).read_to_end(&mut head)?;
+                if head.len() <= size {
+                    return Ok(raw_cid(self.profile.hash()?, &head));
+                }
+                Box::new(std::io::Cursor::new(head).chain(data))
+            }
+            None => data,
+        };
+        let cid = self
+            .ipfs_rpc
+            .hash_data_with_options(self.profile.hash()?, &self.add_options, data)
+            .await?;
+        Ok(cid)
+    }
+
+    async fn add_prepared(&self, data: BoxedReader) -> Result<Cid, LeakyError> {
+        let cid = self
+            .ipfs_rpc
+            .add_data_with_options(self.profile.hash()?, &self.add_options, data)
+            .await?;
         Ok(cid)
     }
 
@@ -548,9 +1509,11 @@ impl Leaky {
     where
         B: TryFrom<Ipld>,
     {
-        let data = self.ipfs_rpc.get_block_send_safe(cid).await?;
-        let block = Block::<DefaultParams>::new(*cid, data).unwrap();
-        let ipld = block.decode::<DagCborCodec, Ipld>().unwrap();
+        let data = self.ipfs_rpc.get_block(cid).await?;
+        let block = Block::<DefaultParams>::new(*cid, data).map_err(|_| LeakyError::Ipld)?;
+        let ipld = block
+            .decode::<IpldCodec, Ipld>()
+            .map_err(|_| LeakyError::Ipld)?;
         let object = B::try_from(ipld).map_err(|_| LeakyError::Ipld)?;
         Ok(object)
     }
@@ -561,11 +1524,14 @@ impl Leaky {
     {
         let ipld: Ipld = object.clone().into();
         let block =
-            Block::<DefaultParams>::encode(DagCborCodec, MhCode::Blake3_256, &ipld).unwrap();
+            Block::<DefaultParams>::encode(self.profile.codec()?, self.profile.hash()?, &ipld)
+                .unwrap();
         let cursor = std::io::Cursor::new(block.data().to_vec());
+        #[cfg(test)]
+        self.puts.fetch_add(1, Ordering::SeqCst);
         let cid = self
             .ipfs_rpc
-            .put_block(IpldCodec::DagCbor, MhCode::Blake3_256, cursor)
+            .put_block(self.profile.codec()?, self.profile.hash()?, cursor)
             .await?;
         Ok(cid)
     }
@@ -590,8 +1556,8 @@ impl Leaky {
         B: Into<Ipld> + Clone,
     {
         let block = Block::<DefaultParams>::encode(
-            DagCborCodec,
-            MhCode::Blake3_256,
+            self.profile.codec()?,
+            self.profile.hash()?,
             &object.clone().into(),
         )
         .unwrap();
@@ -615,6 +1581,12 @@ pub enum LeakyError {
     LeakyApi(#[from] LeakyApiError),
     #[error("serde error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("manifest error: {0}")]
+    Manifest(#[from] ManifestError),
+    #[error("codec profile error: {0}")]
+    CodecProfile(#[from] CodecProfileError),
     #[error("could not convert Ipld to type")]
     Ipld,
     #[error("cid is not set")]
@@ -623,6 +1595,22 @@ pub enum LeakyError {
     PathNotDir(PathBuf),
     #[error("path is not file: {0}")]
     PathNotFile(PathBuf),
+    #[error("path does not exist: {0}")]
+    PathDoesNotExist(PathBuf),
+    #[error("path already exists: {0}")]
+    PathExists(PathBuf),
+    #[error("state cid {0} doesn't match its manifest, which hashes to {1}. Re-pull to recover")]
+    CidMismatch(Cid, Cid),
+    #[error("paths overlap: {0} and {1}")]
+    OverlappingPaths(PathBuf, PathBuf),
+    #[error("cid does not point to a node: {0}")]
+    NotNode(Cid),
+    #[error("name is reserved within the bucket: {0}")]
+    ReservedName(PathBuf),
+    #[error("unsupported compression: {0}")]
+    UnsupportedCompression(String),
+    #[error("block cache entries don't match their cids: {}", .0.join(", "))]
+    CorruptCache(Vec<String>),
 }
 
 #[cfg(test)]
@@ -684,6 +1672,33 @@ mod test {
         assert_eq!(data, get_data);
     }
 
+    #[tokio::test]
+    async fn cat_dir_or_missing() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        leaky
+            .add(&PathBuf::from("/dir/foo"), "foo".as_bytes(), None, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            leaky.cat(&PathBuf::from("/dir/foo")).await.unwrap(),
+            b"foo".to_vec()
+        );
+        assert!(matches!(
+            leaky.cat(&PathBuf::from("/dir")).await,
+            Err(LeakyError::PathNotFile(_))
+        ));
+        assert!(matches!(
+            leaky.cat(&PathBuf::from("/dir/bar")).await,
+            Err(LeakyError::PathDoesNotExist(_))
+        ));
+        assert!(matches!(
+            leaky.cat(&PathBuf::from("/dir/foo/bar")).await,
+            Err(LeakyError::PathDoesNotExist(_))
+        ));
+    }
+
     #[tokio::test]
     async fn add_ls() {
         let cid = empty_leaky_cid().await;
@@ -740,6 +1755,912 @@ mod test {
         assert_eq!(leaky.ls(PathBuf::from("/")).await.unwrap().len(), 1);
     }
 
+    #[tokio::test]
+    async fn add_tag_merge() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let path = PathBuf::from("/foo");
+        let data = "foo".as_bytes();
+        leaky.add(&path, data, None, true).await.unwrap();
+
+        let mut a = BTreeMap::new();
+        a.insert("a".to_string(), Ipld::Integer(1));
+        let mut b = BTreeMap::new();
+        b.insert("b".to_string(), Ipld::Integer(2));
+
+        leaky.tag(&path, &a, false).await.unwrap();
+        leaky.tag(&path, &b, true).await.unwrap();
+        let object = leaky.get_object(&path).await.unwrap().unwrap();
+        assert_eq!(object.metadata().get("a"), Some(&Ipld::Integer(1)));
+        assert_eq!(object.metadata().get("b"), Some(&Ipld::Integer(2)));
+
+        leaky.tag(&path, &b, false).await.unwrap();
+        let object = leaky.get_object(&path).await.unwrap().unwrap();
+        assert_eq!(object.metadata().get("a"), None);
+        assert_eq!(object.metadata().get("b"), Some(&Ipld::Integer(2)));
+    }
+
+    #[tokio::test]
+    async fn tag_missing_path() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let path = PathBuf::from("/foo/bar");
+        let mut metadata = BTreeMap::new();
+        metadata.insert("a".to_string(), Ipld::Integer(1));
+
+        let err = leaky.tag(&path, &metadata, false).await.unwrap_err();
+        assert!(matches!(err, LeakyError::PathDoesNotExist(p) if p == path));
+        // Nothing should have been written
+        assert_eq!(leaky.cid().unwrap(), cid);
+        assert!(leaky.ls(&PathBuf::from("/")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn push_cid_mismatch() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        assert_eq!(leaky.computed_cid().unwrap(), cid);
+        leaky
+            .add(&PathBuf::from("/foo"), "foo".as_bytes(), None, false)
+            .await
+            .unwrap();
+
+        // Load the new manifest under the old cid, as a bad save might have
+        let manifest = leaky.manifest().unwrap();
+        let block_cache = leaky.block_cache().unwrap();
+        let mut corrupt = Leaky::default();
+        corrupt.load(&cid, &manifest, block_cache).await.unwrap();
+        assert!(matches!(
+            corrupt.push().await,
+            Err(LeakyError::CidMismatch(stored, _)) if stored == cid
+        ));
+        // Nothing was committed
+        assert_eq!(corrupt.cid().unwrap(), cid);
+    }
+
+    #[tokio::test]
+    async fn touch() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let path = PathBuf::from("/foo");
+        let mut metadata = BTreeMap::new();
+        metadata.insert("a".to_string(), Ipld::Integer(1));
+        let data_cid = leaky
+            .add(&path, "foo".as_bytes(), Some(&metadata), true)
+            .await
+            .unwrap();
+        let before = leaky.get_object(&path).await.unwrap().unwrap();
+        let root_before = leaky.cid().unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        leaky.touch(&path).await.unwrap();
+        let after = leaky.get_object(&path).await.unwrap().unwrap();
+        assert!(after.updated_at() > before.updated_at());
+        assert_eq!(after.created_at(), before.created_at());
+        assert_eq!(after.metadata(), before.metadata());
+        assert_ne!(leaky.cid().unwrap(), root_before);
+        let items = leaky.items().await.unwrap();
+        assert_eq!(items, vec![(path.clone(), data_cid)]);
+
+        assert!(matches!(
+            leaky.touch(&PathBuf::from("/missing")).await,
+            Err(LeakyError::PathDoesNotExist(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn load_from_cache_offline() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let path = PathBuf::from("/foo/bar");
+        leaky
+            .add(&path, "bar".as_bytes(), None, true)
+            .await
+            .unwrap();
+        let cid = leaky.cid().unwrap();
+        let manifest = leaky.manifest().unwrap();
+        let mut block_cache = leaky.block_cache().unwrap();
+        block_cache.insert(cid_string(&cid), manifest.clone().into());
+
+        // Nothing is listening here
+        let unreachable = Url::parse("http://localhost:1").unwrap();
+        let mut offline = Leaky::new(unreachable.clone(), unreachable.clone()).unwrap();
+        offline
+            .load_from_cache(&cid, block_cache.clone())
+            .await
+            .unwrap();
+        assert_eq!(offline.cid().unwrap(), cid);
+        assert_eq!(offline.manifest().unwrap().data(), manifest.data());
+        let ls = offline.ls(&PathBuf::from("/foo")).await.unwrap();
+        assert!(ls.iter().any(|(name, _)| name == "bar"));
+        assert!(offline.get_object(&path).await.unwrap().is_some());
+
+        // Without the manifest in the cache, it has to go to ipfs
+        block_cache.remove(&cid_string(&cid));
+        let mut offline = Leaky::new(unreachable.clone(), unreachable).unwrap();
+        assert!(offline.load_from_cache(&cid, block_cache).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn render_tree() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let bar = leaky
+            .add(&PathBuf::from("/foo/bar"), "bar".as_bytes(), None, true)
+            .await
+            .unwrap();
+        let qux = leaky
+            .add(&PathBuf::from("/foo/baz/qux"), "qux".as_bytes(), None, true)
+            .await
+            .unwrap();
+        let top = leaky
+            .add(&PathBuf::from("/top"), "top".as_bytes(), None, true)
+            .await
+            .unwrap();
+        let mut metadata = BTreeMap::new();
+        metadata.insert("kind".to_string(), Ipld::String("text".to_string()));
+        leaky
+            .tag(&PathBuf::from("/top"), &metadata, false)
+            .await
+            .unwrap();
+
+        let dir_cid = |entries: Vec<(String, (Cid, Option<Object>))>, name: &str| {
+            entries.into_iter().find(|(n, _)| n == name).unwrap().1 .0
+        };
+        let root = *leaky.manifest().unwrap().data();
+        let foo = dir_cid(leaky.ls(&PathBuf::from("/")).await.unwrap(), "foo");
+        let baz = dir_cid(leaky.ls(&PathBuf::from("/foo")).await.unwrap(), "baz");
+        let expected = [
+            format!("/ [dir] {}", root),
+            format!("  foo/ [dir] {}", foo),
+            format!("    bar [file] {}", bar),
+            format!("    baz/ [dir] {}", baz),
+            format!("      qux [file] {}", qux),
+            format!("  top [file] {}", top),
+        ]
+        .join("\n");
+        assert_eq!(leaky.render_tree(false).await.unwrap(), expected);
+
+        let tree = leaky.render_tree(true).await.unwrap();
+        let top_line = tree.lines().last().unwrap();
+        assert!(top_line.starts_with(&format!("  top [file] {} (updated ", top)));
+        assert!(top_line.ends_with(", metadata: kind)"));
+    }
+
+    #[tokio::test]
+    async fn history() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let mut pushed = vec![cid];
+        for i in 0..2 {
+            let path = PathBuf::from(format!("/{}", i));
+            leaky
+                .add(&path, format!("{}", i).as_bytes(), None, true)
+                .await
+                .unwrap();
+            leaky.push().await.unwrap();
+            pushed.push(leaky.cid().unwrap());
+        }
+
+        let history = leaky.history(None).await.unwrap();
+        assert_eq!(history[0].0, leaky.cid().unwrap());
+        // Each entry points at the one after it, ending at the bucket's first manifest
+        for pair in history.windows(2) {
+            assert_eq!(pair[0].1.previous(), &pair[1].0);
+        }
+        assert_eq!(history.last().unwrap().1.previous(), &Cid::default());
+        // Every push shows up, newest first
+        let positions = pushed
+            .iter()
+            .rev()
+            .map(|cid| history.iter().position(|(c, _)| c == cid).unwrap())
+            .collect::<Vec<_>>();
+        assert!(positions.windows(2).all(|p| p[0] < p[1]));
+
+        assert_eq!(leaky.history(Some(2)).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn swap() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let a = PathBuf::from("/a.txt");
+        let b = PathBuf::from("/dir/b.txt");
+        let mut a_metadata = BTreeMap::new();
+        a_metadata.insert("name".to_string(), Ipld::String("a".to_string()));
+        let mut b_metadata = BTreeMap::new();
+        b_metadata.insert("name".to_string(), Ipld::String("b".to_string()));
+        leaky
+            .add(&a, "a".as_bytes(), Some(&a_metadata), false)
+            .await
+            .unwrap();
+        leaky
+            .add(&b, "b".as_bytes(), Some(&b_metadata), false)
+            .await
+            .unwrap();
+
+        leaky.swap(&a, &b).await.unwrap();
+        assert_eq!(leaky.cat(&a).await.unwrap(), b"b".to_vec());
+        assert_eq!(leaky.cat(&b).await.unwrap(), b"a".to_vec());
+        let a_object = leaky.get_object(&a).await.unwrap().unwrap();
+        assert_eq!(a_object.metadata(), &b_metadata);
+        let b_object = leaky.get_object(&b).await.unwrap().unwrap();
+        assert_eq!(b_object.metadata(), &a_metadata);
+
+        // Swapping back restores the original tree
+        leaky.swap(&a, &b).await.unwrap();
+        assert_eq!(leaky.cat(&a).await.unwrap(), b"a".to_vec());
+
+        assert!(matches!(
+            leaky.swap(&a, &PathBuf::from("/missing")).await,
+            Err(LeakyError::PathDoesNotExist(_))
+        ));
+        assert!(matches!(
+            leaky.swap(&PathBuf::from("/dir"), &b).await,
+            Err(LeakyError::OverlappingPaths(_, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn inspect_json() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        leaky
+            .add(&PathBuf::from("/foo/bar"), "bar".as_bytes(), None, true)
+            .await
+            .unwrap();
+        leaky
+            .add(&PathBuf::from("/baz"), "baz".as_bytes(), None, true)
+            .await
+            .unwrap();
+        let data_cid = *leaky.manifest().unwrap().data();
+
+        // Unpushed nodes come straight out of the cache
+        let json = leaky.inspect_json(&data_cid).await.unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(value.get("foo").unwrap().get("/").is_some());
+        assert!(value.get("baz").unwrap().get("/").is_some());
+
+        // As does the manifest, once it's on the node
+        leaky.push().await.unwrap();
+        let json = Leaky::default()
+            .inspect_json(&leaky.cid().unwrap())
+            .await
+            .unwrap();
+        assert!(json.contains(&data_cid.to_string()));
+
+        let raw_cid = leaky.hash_data("baz".as_bytes()).await.unwrap();
+        assert!(matches!(
+            leaky.inspect(&raw_cid).await,
+            Err(LeakyError::NotNode(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn mkdir_push_pull() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        leaky.mkdir(&PathBuf::from("/foo/empty")).await.unwrap();
+        leaky.push().await.unwrap();
+
+        let cid = leaky.cid().unwrap();
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        assert_eq!(
+            leaky.empty_dirs().await.unwrap(),
+            vec![PathBuf::from("/foo/empty")]
+        );
+        assert!(leaky.items().await.unwrap().is_empty());
+        assert!(leaky
+            .ls(&PathBuf::from("/foo/empty"))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn mkdir_existing() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        leaky
+            .add(&PathBuf::from("/foo/bar"), "bar".as_bytes(), None, true)
+            .await
+            .unwrap();
+        let cid = leaky.cid().unwrap();
+
+        // An existing directory is left as it is
+        leaky.mkdir(&PathBuf::from("/foo")).await.unwrap();
+        assert_eq!(leaky.cid().unwrap(), cid);
+
+        // A file can't be replaced by a directory without removing it first
+        match leaky.mkdir(&PathBuf::from("/foo/bar")).await {
+            Err(LeakyError::PathExists(path)) => assert_eq!(path, PathBuf::from("foo/bar")),
+            other => panic!("expected PathExists, got {:?}", other),
+        }
+        assert_eq!(leaky.cid().unwrap(), cid);
+    }
+
+    #[tokio::test]
+    async fn add_cache_stats() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let stats = leaky.cache_stats().unwrap();
+        assert_eq!(stats.node_count, 1);
+        assert_eq!(stats.data_link_count, 0);
+
+        leaky
+            .add(&PathBuf::from("/foo"), "foo".as_bytes(), None, true)
+            .await
+            .unwrap();
+        leaky
+            .add(&PathBuf::from("/bar/buzz"), "buzz".as_bytes(), None, true)
+            .await
+            .unwrap();
+        leaky.push().await.unwrap();
+
+        // Pull fresh so stale versions of the nodes aren't counted
+        let cid = leaky.cid().unwrap();
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let stats = leaky.cache_stats().unwrap();
+        assert_eq!(stats.node_count, 2);
+        assert_eq!(stats.data_link_count, 2);
+        assert!(stats.cache_bytes > 0);
+    }
+
+    #[tokio::test]
+    async fn codec_profile_cids() {
+        use crate::types::{CidVersion, MhCode};
+
+        let node = Node::default();
+        let a = Leaky::default();
+        let b = Leaky::default();
+        let mut c = Leaky::default();
+        c.profile =
+            CodecProfile::new(MhCode::Sha2_256, IpldCodec::DagCbor, CidVersion::V1).unwrap();
+
+        let a_cid = a.put_cache::<Node>(&node).await.unwrap();
+        let b_cid = b.put_cache::<Node>(&node).await.unwrap();
+        let c_cid = c.put_cache::<Node>(&node).await.unwrap();
+        assert_eq!(a_cid, b_cid);
+        assert_ne!(a_cid, c_cid);
+        assert_eq!(c_cid.hash().code(), 0x12);
+    }
+
+    #[tokio::test]
+    async fn set_default_add() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let dir = PathBuf::from("/docs");
+        leaky.mkdir(&dir).await.unwrap();
+
+        let mut template = BTreeMap::new();
+        template.insert("author".to_string(), Ipld::String("leaky".to_string()));
+        leaky.set_default(&dir, Some(&template)).await.unwrap();
+
+        let path = PathBuf::from("/docs/foo");
+        leaky
+            .add(&path, "foo".as_bytes(), None, true)
+            .await
+            .unwrap();
+        let object = leaky.get_object(&path).await.unwrap().unwrap();
+        assert_eq!(object.metadata(), &template);
+
+        // Explicit metadata wins over the default
+        let mut metadata = BTreeMap::new();
+        metadata.insert("author".to_string(), Ipld::String("me".to_string()));
+        let path = PathBuf::from("/docs/bar");
+        leaky
+            .add(&path, "bar".as_bytes(), Some(&metadata), true)
+            .await
+            .unwrap();
+        let object = leaky.get_object(&path).await.unwrap().unwrap();
+        assert_eq!(object.metadata(), &metadata);
+    }
+
+    #[test]
+    fn node_default_round_trip() {
+        let mut template = BTreeMap::new();
+        template.insert("author".to_string(), Ipld::String("leaky".to_string()));
+        let mut node = Node::default();
+        node.set_default(Some(&template));
+        let ipld: Ipld = node.clone().into();
+        let node = Node::try_from(ipld).unwrap();
+        assert_eq!(node.get_default(), Some(template));
+        assert_eq!(node.size(), 0);
+        assert!(node.get_links().is_empty());
+    }
+
+    #[tokio::test]
+    async fn push_missing_blocks() {
+        use rand::Rng;
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+
+        let data: Vec<u8> = (0..1024).map(|_| rand::thread_rng().gen()).collect();
+        leaky
+            .add(
+                &PathBuf::from("/foo"),
+                std::io::Cursor::new(data),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+        leaky.push().await.unwrap();
+        assert!(leaky.missing_blocks(4).await.unwrap().is_empty());
+
+        // Only hashing the data means it never makes it to the remote
+        let data: Vec<u8> = (0..1024).map(|_| rand::thread_rng().gen()).collect();
+        let data_cid = leaky
+            .add(
+                &PathBuf::from("/bar"),
+                std::io::Cursor::new(data),
+                None,
+                true,
+            )
+            .await
+            .unwrap();
+        leaky.push().await.unwrap();
+        assert_eq!(leaky.missing_blocks(4).await.unwrap(), vec![data_cid]);
+    }
+
+    #[tokio::test]
+    async fn add_many() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+
+        let items = (0..100)
+            .map(|i| {
+                let path = PathBuf::from(format!("/many/{:03}", i));
+                let data = std::io::Cursor::new(format!("blob {}", i).into_bytes());
+                (path, data, true)
+            })
+            .collect::<Vec<_>>();
+        let puts = leaky.puts.load(Ordering::SeqCst);
+        let data_cids = leaky.add_many(items).await.unwrap();
+        assert_eq!(data_cids.len(), 100);
+        assert_ne!(leaky.cid().unwrap(), cid);
+        // The manifest is only written once for the whole batch
+        assert_eq!(leaky.puts.load(Ordering::SeqCst), puts + 1);
+
+        let items = leaky.items().await.unwrap();
+        assert_eq!(items.len(), 100);
+        assert_eq!(items[7].0, PathBuf::from("/many/007"));
+        assert_eq!(items[7].1, data_cids[7]);
+
+        // Where adding one at a time writes it for every item
+        let mut serial = Leaky::default();
+        serial.pull(&cid).await.unwrap();
+        let puts = serial.puts.load(Ordering::SeqCst);
+        for i in 0..100 {
+            let path = PathBuf::from(format!("/many/{:03}", i));
+            let data = std::io::Cursor::new(format!("blob {}", i).into_bytes());
+            serial.add(&path, data, None, true).await.unwrap();
+        }
+        assert_eq!(serial.puts.load(Ordering::SeqCst), puts + 100);
+        assert_eq!(serial.items().await.unwrap(), items);
+    }
+
+    #[tokio::test]
+    async fn add_many_concurrent() {
+        let cid = empty_leaky_cid().await;
+        let items = || {
+            (0..64)
+                .map(|i| {
+                    let path = PathBuf::from(format!("/many/{:02}/{}", i % 8, i));
+                    // Big enough that some of these need a trip to the node
+                    let data = format!("blob {}", i).repeat(i * 1024);
+                    (path, std::io::Cursor::new(data.into_bytes()), i % 2 == 0)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut serial = Leaky::default();
+        serial.pull(&cid).await.unwrap();
+        serial.set_add_concurrency(1);
+        let serial_cids = serial.add_many(items()).await.unwrap();
+
+        let mut concurrent = Leaky::default();
+        concurrent.pull(&cid).await.unwrap();
+        concurrent.set_add_concurrency(16);
+        let concurrent_cids = concurrent.add_many(items()).await.unwrap();
+
+        // Same data cids, in the order the items were given. Roots differ, since
+        //  every object is stamped with when it was added
+        assert_eq!(concurrent_cids, serial_cids);
+        let items = concurrent.items().await.unwrap();
+        assert_eq!(items, serial.items().await.unwrap());
+        assert_eq!(items.len(), 64);
+        concurrent.integrity_check().unwrap();
+    }
+
+    #[tokio::test]
+    async fn pull_wide_tree() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let items = (0..32)
+            .flat_map(|i| {
+                (0..2).map(move |j| {
+                    let path = PathBuf::from(format!("/wide/{:02}/{}", i, j));
+                    let data = std::io::Cursor::new(format!("blob {} {}", i, j).into_bytes());
+                    (path, data, true)
+                })
+            })
+            .collect::<Vec<_>>();
+        leaky.add_many(items).await.unwrap();
+        leaky.push().await.unwrap();
+
+        for concurrency in [1, 4] {
+            let mut pulled = Leaky::default();
+            pulled.set_pull_concurrency(concurrency);
+            pulled.pull(&leaky.cid().unwrap()).await.unwrap();
+            assert_eq!(pulled.items().await.unwrap(), leaky.items().await.unwrap());
+            // The data node, /wide, and each of its directories
+            assert_eq!(pulled.block_cache().unwrap().len(), 34);
+            pulled.integrity_check().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn import_unixfs() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+
+        // Seed a UnixFS directory through MFS
+        let root = format!("/leaky-import-{}", rand::random::<u64>());
+        let ipfs_rpc = leaky.ipfs_rpc.clone();
+        ipfs_rpc
+            .files_mkdir(&format!("{}/sub", root), true)
+            .await
+            .unwrap();
+        ipfs_rpc
+            .files_mkdir(&format!("{}/empty", root), true)
+            .await
+            .unwrap();
+        ipfs_rpc
+            .files_write(&format!("{}/foo", root), true, true, "foo".as_bytes())
+            .await
+            .unwrap();
+        ipfs_rpc
+            .files_write(&format!("{}/sub/bar", root), true, true, "bar".as_bytes())
+            .await
+            .unwrap();
+        let stat = ipfs_rpc.files_stat(&root).await.unwrap();
+        let dir_cid = Cid::try_from(stat.hash).unwrap();
+
+        leaky
+            .import_unixfs(&PathBuf::from("/imported"), &dir_cid)
+            .await
+            .unwrap();
+
+        let items = leaky
+            .items()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, _cid)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                PathBuf::from("/imported/foo"),
+                PathBuf::from("/imported/sub/bar")
+            ]
+        );
+        assert_eq!(
+            leaky.empty_dirs().await.unwrap(),
+            vec![PathBuf::from("/imported/empty")]
+        );
+        let data = leaky
+            .cat(&PathBuf::from("/imported/sub/bar"))
+            .await
+            .unwrap();
+        assert_eq!(data, "bar".as_bytes());
+
+        ipfs_rpc.files_rm(&root, true).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn graft() {
+        // Build a subtree in one bucket
+        let cid = empty_leaky_cid().await;
+        let mut library = Leaky::default();
+        library.pull(&cid).await.unwrap();
+        library
+            .add(&PathBuf::from("/assets/foo"), "foo".as_bytes(), None, false)
+            .await
+            .unwrap();
+        library
+            .add(
+                &PathBuf::from("/assets/img/bar"),
+                "bar".as_bytes(),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+        library.push().await.unwrap();
+        let (_name, (assets_cid, _object)) = library
+            .ls(&PathBuf::from("/"))
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|(name, _)| name == "assets")
+            .unwrap();
+
+        // And graft it into another
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        leaky
+            .add(&PathBuf::from("/lib/old"), "old".as_bytes(), None, true)
+            .await
+            .unwrap();
+        leaky
+            .graft(&PathBuf::from("/lib"), &assets_cid)
+            .await
+            .unwrap();
+
+        let items = leaky
+            .items()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, _cid)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![PathBuf::from("/lib/foo"), PathBuf::from("/lib/img/bar")]
+        );
+        let data = leaky.cat(&PathBuf::from("/lib/img/bar")).await.unwrap();
+        assert_eq!(data, "bar".as_bytes());
+    }
+
+    #[tokio::test]
+    async fn pull_raw_data_cid() {
+        let leaky = Leaky::default();
+        let raw_cid = leaky.add_data("foo".as_bytes()).await.unwrap();
+        let mut manifest = Manifest::default();
+        manifest.set_data(raw_cid);
+        let manifest_cid = leaky.put::<Manifest>(&manifest).await.unwrap();
+
+        let mut leaky = Leaky::default();
+        match leaky.pull(&manifest_cid).await {
+            Err(LeakyError::Manifest(ManifestError::DataNotNode(cid))) => {
+                assert_eq!(cid, raw_cid)
+            }
+            Err(e) => panic!("expected DataNotNode, got {}", e),
+            Ok(_) => panic!("expected DataNotNode"),
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_from() {
+        let cid = empty_leaky_cid().await;
+        let mut source = Leaky::default();
+        source.pull(&cid).await.unwrap();
+        let mut metadata = BTreeMap::new();
+        metadata.insert("foo".to_string(), Ipld::String("bar".to_string()));
+        source
+            .add(
+                &PathBuf::from("/docs/foo"),
+                "foo".as_bytes(),
+                Some(&metadata),
+                false,
+            )
+            .await
+            .unwrap();
+        source.push().await.unwrap();
+
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        leaky
+            .copy_from(&source, &PathBuf::from("/docs/foo"), &PathBuf::from("/foo"))
+            .await
+            .unwrap();
+        leaky
+            .copy_from(&source, &PathBuf::from("/docs"), &PathBuf::from("/copied"))
+            .await
+            .unwrap();
+
+        let items = leaky
+            .items()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, _cid)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![PathBuf::from("/copied/foo"), PathBuf::from("/foo")]
+        );
+        let object = leaky
+            .get_object(&PathBuf::from("/foo"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(object.metadata(), &metadata);
+        let data = leaky.cat(&PathBuf::from("/copied/foo")).await.unwrap();
+        assert_eq!(data, "foo".as_bytes());
+    }
+
+    #[tokio::test]
+    async fn ref_counts_prune() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let foo = leaky
+            .add(&PathBuf::from("/a/foo"), "foo".as_bytes(), None, true)
+            .await
+            .unwrap();
+        leaky
+            .add(&PathBuf::from("/b/foo"), "foo".as_bytes(), None, true)
+            .await
+            .unwrap();
+        assert_eq!(leaky.ref_counts().await.unwrap().get(&foo), Some(&2));
+
+        leaky.rm(&PathBuf::from("/a/foo")).await.unwrap();
+        leaky.prune().await.unwrap();
+        let counts = leaky.ref_counts().await.unwrap();
+        assert_eq!(counts.get(&foo), Some(&1));
+
+        // Only reachable nodes are left, and the tree is intact
+        let stats = leaky.cache_stats().unwrap();
+        assert_eq!(
+            stats.node_count,
+            counts.keys().filter(|cid| cid.is_node_cid()).count()
+        );
+        let items = leaky.items().await.unwrap();
+        assert_eq!(items, vec![(PathBuf::from("/b/foo"), foo)]);
+    }
+
+    // Produces `len` bytes without ever holding them, recording how much was read
+    //  and the largest single read asked of it
+    struct CountingReader {
+        len: usize,
+        read: Arc<std::sync::atomic::AtomicUsize>,
+        max_read: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            use std::sync::atomic::Ordering;
+            let read = self.read.load(Ordering::SeqCst);
+            let n = buf.len().min(self.len - read);
+            for (i, byte) in buf[..n].iter_mut().enumerate() {
+                *byte = ((read + i) % 251) as u8;
+            }
+            self.read.fetch_add(n, Ordering::SeqCst);
+            self.max_read.fetch_max(buf.len(), Ordering::SeqCst);
+            Ok(n)
+        }
+    }
+
+    #[tokio::test]
+    async fn hash_data_streams() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let len = 16 * 1024 * 1024;
+        let read = Arc::new(AtomicUsize::new(0));
+        let max_read = Arc::new(AtomicUsize::new(0));
+        let reader = CountingReader {
+            len,
+            read: read.clone(),
+            max_read: max_read.clone(),
+        };
+        let leaky = Leaky::default();
+        leaky.hash_data(reader).await.unwrap();
+        assert_eq!(read.load(Ordering::SeqCst), len);
+        // Nothing along the way should try and read the whole thing in at once
+        assert!(max_read.load(Ordering::SeqCst) <= 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn add_compressed_cat() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        leaky.set_compress(true);
+        let text = "all work and no play makes jack a dull boy\n".repeat(1000);
+        let data_cid = leaky
+            .add(
+                &PathBuf::from("/text.txt"),
+                std::io::Cursor::new(text.clone().into_bytes()),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+
+        // Stored compressed, and marked as such
+        let stored = leaky.ipfs_rpc.cat_data(&data_cid).await.unwrap();
+        assert!(stored.len() < text.len());
+        let object = leaky
+            .get_object(&PathBuf::from("/text.txt"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            object.metadata().get(COMPRESSION_KEY),
+            Some(&Ipld::String(ZSTD_COMPRESSION.to_string()))
+        );
+        let data = leaky.cat(&PathBuf::from("/text.txt")).await.unwrap();
+        assert_eq!(data, text.as_bytes());
+
+        // Replacing the metadata doesn't break reads
+        let mut metadata = BTreeMap::new();
+        metadata.insert("foo".to_string(), Ipld::String("bar".to_string()));
+        leaky
+            .tag(&PathBuf::from("/text.txt"), &metadata, false)
+            .await
+            .unwrap();
+        let data = leaky.cat(&PathBuf::from("/text.txt")).await.unwrap();
+        assert_eq!(data, text.as_bytes());
+
+        // Incompressible data is stored as-is
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let noise: Vec<u8> = (0..1024).map(|_| rng.gen()).collect();
+        leaky
+            .add(
+                &PathBuf::from("/noise"),
+                std::io::Cursor::new(noise.clone()),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+        let object = leaky
+            .get_object(&PathBuf::from("/noise"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(object.metadata().get(COMPRESSION_KEY).is_none());
+        assert_eq!(leaky.cat(&PathBuf::from("/noise")).await.unwrap(), noise);
+    }
+
+    #[tokio::test]
+    async fn integrity_check() {
+        let mut leaky = Leaky::default();
+        let node = Node::default();
+        let cid = leaky.put_cache::<Node>(&node).await.unwrap();
+        let mut manifest = Manifest::default();
+        manifest.set_data(cid);
+        let block_cache = leaky.block_cache().unwrap();
+        leaky
+            .load(&cid, &manifest, block_cache.clone())
+            .await
+            .unwrap();
+        leaky.integrity_check().unwrap();
+
+        // Store the node under someone else's cid
+        let mut corrupted = block_cache;
+        let ipld = corrupted.remove(&cid_string(&cid)).unwrap();
+        let wrong_cid = Cid::default().to_string();
+        corrupted.insert(wrong_cid.clone(), ipld);
+        leaky.load(&cid, &manifest, corrupted).await.unwrap();
+        match leaky.integrity_check() {
+            Err(LeakyError::CorruptCache(keys)) => assert_eq!(keys, vec![wrong_cid]),
+            other => panic!("expected CorruptCache, got {:?}", other),
+        }
+    }
+
     #[tokio::test]
     async fn add_add_deep() {
         let cid = empty_leaky_cid().await;
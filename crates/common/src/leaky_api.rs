@@ -1,9 +1,17 @@
-use std::str::FromStr;
+use std::time::Duration;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::types::Cid;
+use crate::types::{parse_leaky_cid, Cid};
+
+/* Constants */
+
+// How many times to retry a request before giving up
+const REQUEST_RETRIES: u32 = 3;
+// Base delay between retries, doubled on each attempt
+const REQUEST_BACKOFF_MS: u64 = 250;
 
 /* Ipfs Rpc Client Wrapper */
 
@@ -34,41 +42,141 @@ impl TryFrom<Url> for LeakyApi {
     }
 }
 
+/* Requests */
+
+/// A request against one of the api routes, and the response it gets back
+pub trait ApiRequest: Serialize {
+    type Response: DeserializeOwned;
+
+    /// Whether the request can be safely repeated after a transient failure
+    const RETRYABLE: bool = false;
+
+    fn method(&self) -> reqwest::Method;
+
+    /// Path of the route, relative to the api's base url
+    fn path(&self) -> String;
+}
+
+/// Set the bucket's root, given the root it's replacing
 #[derive(Debug, Serialize)]
 pub struct PushRootRequest {
     cid: String,
     previous_cid: String,
 }
 
+impl PushRootRequest {
+    pub fn new(cid: &Cid, previous_cid: &Cid) -> Self {
+        Self {
+            cid: cid.to_string(),
+            previous_cid: previous_cid.to_string(),
+        }
+    }
+}
+
+impl ApiRequest for PushRootRequest {
+    type Response = ();
+    // The blocks are already pinned by the time this is sent, and repeating
+    //  the same root is harmless
+    const RETRYABLE: bool = true;
+
+    fn method(&self) -> reqwest::Method {
+        reqwest::Method::POST
+    }
+
+    fn path(&self) -> String {
+        "api/v0/root".to_string()
+    }
+}
+
+/// Get the bucket's current root
+#[derive(Debug, Serialize)]
+pub struct PullRootRequest;
+
+impl ApiRequest for PullRootRequest {
+    type Response = PullRootResponse;
+
+    fn method(&self) -> reqwest::Method {
+        reqwest::Method::GET
+    }
+
+    fn path(&self) -> String {
+        "api/v0/root".to_string()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PullRootResponse {
     cid: String,
 }
 
+impl PullRootResponse {
+    pub fn cid(&self) -> Result<Cid, LeakyApiError> {
+        parse_leaky_cid(&self.cid).map_err(|e| LeakyApiError::Default(e.into()))
+    }
+}
+
+// Routes with nothing to say respond with an empty body, which reads as null
+fn decode_response<R: DeserializeOwned>(body: &str) -> Result<R, LeakyApiError> {
+    let body = if body.trim().is_empty() { "null" } else { body };
+    Ok(serde_json::from_str(body)?)
+}
+
 impl LeakyApi {
-    pub async fn push_root(&self, cid: &Cid, previous_cid: &Cid) -> Result<(), LeakyApiError> {
-        let url = self.base_url.join("api/v0/root")?;
-        let body = serde_json::to_string(&PushRootRequest {
-            cid: cid.to_string(),
-            previous_cid: previous_cid.to_string(),
-        })?;
-        // push_root
-        let response = self.client.post(url).body(body).send().await?;
+    /// Send a request, retrying transient failures if the request allows it
+    pub async fn call<R: ApiRequest>(&self, request: &R) -> Result<R::Response, LeakyApiError> {
+        let url = self.base_url.join(&request.path())?;
+        let method = request.method();
+        let body = if method == reqwest::Method::GET {
+            None
+        } else {
+            Some(serde_json::to_string(request)?)
+        };
+        let mut attempt = 0;
+        let response = loop {
+            let mut builder = self.client.request(method.clone(), url.clone());
+            if let Some(body) = &body {
+                builder = builder.body(body.clone());
+            }
+            let result = builder.send().await;
+            let retryable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+            if !R::RETRYABLE || !retryable || attempt >= REQUEST_RETRIES {
+                break result?;
+            }
+            attempt += 1;
+            tokio::time::sleep(Duration::from_millis(
+                REQUEST_BACKOFF_MS * 2u64.pow(attempt - 1),
+            ))
+            .await;
+        };
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            return Err(LeakyApiError::Conflict(response.text().await?));
+        }
         if !response.status().is_success() {
             return Err(LeakyApiError::Api(
                 response.status(),
                 response.text().await?,
             ));
         }
-        Ok(())
+        decode_response(&response.text().await?)
+    }
+
+    /// Set the bucket's root. A conflict where the server's root is already `cid`
+    ///  means an earlier attempt went through, so it counts as success
+    pub async fn push_root(&self, cid: &Cid, previous_cid: &Cid) -> Result<(), LeakyApiError> {
+        match self.call(&PushRootRequest::new(cid, previous_cid)).await {
+            Err(LeakyApiError::Conflict(reason)) => match self.pull_root().await {
+                Ok(current) if &current == cid => Ok(()),
+                _ => Err(LeakyApiError::Conflict(reason)),
+            },
+            result => result,
+        }
     }
 
     pub async fn pull_root(&self) -> Result<Cid, LeakyApiError> {
-        let url = self.base_url.join("api/v0/root")?;
-        let response = self.client.get(url).send().await?;
-        let response = response.text().await?;
-        let response: PullRootResponse = serde_json::from_str(&response)?;
-        Ok(Cid::from_str(&response.cid).unwrap())
+        self.call(&PullRootRequest).await?.cid()
     }
 }
 
@@ -88,9 +196,36 @@ pub enum LeakyApiError {
     Serde(#[from] serde_json::Error),
     #[error("Api error: {0} {1}")]
     Api(reqwest::StatusCode, String),
+    #[error("Root conflict: {0}")]
+    Conflict(String),
 }
 
-/*
 #[cfg(test)]
-mod tests {}
-*/
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_root_request() {
+        let cid = Cid::default();
+        let request = PushRootRequest::new(&cid, &cid);
+        assert_eq!(request.method(), reqwest::Method::POST);
+        assert_eq!(request.path(), "api/v0/root");
+        let body: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["cid"], cid.to_string());
+        assert_eq!(body["previous_cid"], cid.to_string());
+        // The server doesn't say anything back
+        decode_response::<<PushRootRequest as ApiRequest>::Response>("").unwrap();
+    }
+
+    #[test]
+    fn test_pull_root_request() {
+        let request = PullRootRequest;
+        assert_eq!(request.method(), reqwest::Method::GET);
+        assert_eq!(request.path(), "api/v0/root");
+        let cid = Cid::default();
+        let body = format!("{{\"cid\": \"{}\"}}", cid);
+        let response: PullRootResponse = decode_response(&body).unwrap();
+        assert_eq!(response.cid().unwrap(), cid);
+        assert!(decode_response::<PullRootResponse>("{}").is_err());
+    }
+}
@@ -6,11 +6,24 @@ mod leaky_api;
 mod types;
 
 pub mod prelude {
-    pub use crate::leaky::{BlockCache, Leaky, LeakyError};
-    pub use crate::types::{Cid, Ipld, Manifest, Object, Version};
+    pub use crate::ipfs_rpc::{cid_for_bytes, AddOptions, Chunker};
+    pub use crate::leaky::{
+        BlockCache, CacheStats, Leaky, LeakyError, COMPRESSION_KEY, DEFAULT_ADD_CONCURRENCY,
+    };
+    pub use crate::types::{
+        parse_leaky_cid, Cid, CodecProfile, Ipld, LeakyCid, Manifest, Object, Version,
+        RESERVED_NAMES,
+    };
+}
+
+pub mod api {
+    pub use crate::leaky_api::{
+        ApiRequest, LeakyApi, PullRootRequest, PullRootResponse, PushRootRequest,
+    };
 }
 
 pub mod error {
     pub use crate::leaky::LeakyError;
-    pub use crate::types::CidError;
+    pub use crate::leaky_api::LeakyApiError;
+    pub use crate::types::{CidError, CodecProfileError, LeakyCidError, ManifestError};
 }
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use super::ipld::{CidVersion, Ipld, IpldCodec, MhCode};
+
+/// Codec Profile
+/// Determines how blocks within a bucket are encoded and addressed.
+///  Recorded in the manifest so every client produces the same CIDs for the same data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodecProfile {
+    /// Multihash code used to hash blocks and data
+    hash: u64,
+    /// Ipld codec used to encode nodes
+    codec: u64,
+    /// Version of the CIDs produced
+    cid_version: u64,
+}
+
+impl Default for CodecProfile {
+    fn default() -> Self {
+        Self {
+            hash: MhCode::Blake3_256.into(),
+            codec: IpldCodec::DagCbor.into(),
+            cid_version: 1,
+        }
+    }
+}
+
+impl Into<Ipld> for CodecProfile {
+    fn into(self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("hash".to_string(), Ipld::Integer(self.hash as i128));
+        map.insert("codec".to_string(), Ipld::Integer(self.codec as i128));
+        map.insert(
+            "cid_version".to_string(),
+            Ipld::Integer(self.cid_version as i128),
+        );
+        Ipld::Map(map)
+    }
+}
+
+impl TryFrom<Ipld> for CodecProfile {
+    type Error = CodecProfileError;
+    fn try_from(ipld: Ipld) -> Result<Self, CodecProfileError> {
+        let map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(CodecProfileError::MissingField("map".to_string())),
+        };
+        let field = |name: &str| match map.get(name) {
+            Some(Ipld::Integer(i)) => Ok(*i as u64),
+            _ => Err(CodecProfileError::MissingField(name.to_string())),
+        };
+        Ok(Self {
+            hash: check_hash(field("hash")?)?.into(),
+            codec: check_codec(field("codec")?)?.into(),
+            cid_version: check_cid_version(field("cid_version")?)?.into(),
+        })
+    }
+}
+
+impl CodecProfile {
+    pub fn new(
+        hash: MhCode,
+        codec: IpldCodec,
+        cid_version: CidVersion,
+    ) -> Result<Self, CodecProfileError> {
+        Ok(Self {
+            hash: check_hash(hash.into())?.into(),
+            codec: check_codec(codec.into())?.into(),
+            cid_version: check_cid_version(cid_version.into())?.into(),
+        })
+    }
+
+    // Profiles read straight from disk skip `new`, so the fields are checked on the way out
+
+    pub fn hash(&self) -> Result<MhCode, CodecProfileError> {
+        check_hash(self.hash)
+    }
+
+    pub fn codec(&self) -> Result<IpldCodec, CodecProfileError> {
+        check_codec(self.codec)
+    }
+
+    pub fn cid_version(&self) -> Result<CidVersion, CodecProfileError> {
+        check_cid_version(self.cid_version)
+    }
+}
+
+// Only hashes the ipfs node can hash data with
+fn check_hash(hash: u64) -> Result<MhCode, CodecProfileError> {
+    match MhCode::try_from(hash) {
+        Ok(code @ (MhCode::Blake3_256 | MhCode::Sha2_256 | MhCode::Sha3_256)) => Ok(code),
+        _ => Err(CodecProfileError::UnsupportedHash(hash)),
+    }
+}
+
+// Nodes are only ever dag-cbor, which is what makes a cid a node cid
+fn check_codec(codec: u64) -> Result<IpldCodec, CodecProfileError> {
+    match IpldCodec::try_from(codec) {
+        Ok(IpldCodec::DagCbor) => Ok(IpldCodec::DagCbor),
+        _ => Err(CodecProfileError::UnsupportedCodec(codec)),
+    }
+}
+
+// V0 CIDs can only address dag-pb, which can't represent our nodes
+fn check_cid_version(cid_version: u64) -> Result<CidVersion, CodecProfileError> {
+    match CidVersion::try_from(cid_version) {
+        Ok(CidVersion::V1) => Ok(CidVersion::V1),
+        _ => Err(CodecProfileError::UnsupportedCidVersion(cid_version)),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CodecProfileError {
+    #[error("missing field: {0}")]
+    MissingField(String),
+    #[error("unsupported hash: {0:#x}")]
+    UnsupportedHash(u64),
+    #[error("unsupported codec: {0:#x}")]
+    UnsupportedCodec(u64),
+    #[error("unsupported cid version: {0}")]
+    UnsupportedCidVersion(u64),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn supported_profiles() {
+        for hash in [MhCode::Blake3_256, MhCode::Sha2_256, MhCode::Sha3_256] {
+            let profile = CodecProfile::new(hash, IpldCodec::DagCbor, CidVersion::V1).unwrap();
+            assert_eq!(profile.hash().unwrap(), hash);
+            assert_eq!(profile.codec().unwrap(), IpldCodec::DagCbor);
+            let ipld: Ipld = profile.into();
+            assert_eq!(CodecProfile::try_from(ipld).unwrap(), profile);
+        }
+    }
+
+    #[test]
+    fn unsupported_profiles() {
+        assert!(matches!(
+            CodecProfile::new(MhCode::Blake3_256, IpldCodec::DagJson, CidVersion::V1),
+            Err(CodecProfileError::UnsupportedCodec(_))
+        ));
+        assert!(matches!(
+            CodecProfile::new(MhCode::Sha2_512, IpldCodec::DagCbor, CidVersion::V1),
+            Err(CodecProfileError::UnsupportedHash(_))
+        ));
+        assert!(matches!(
+            CodecProfile::new(MhCode::Blake3_256, IpldCodec::DagCbor, CidVersion::V0),
+            Err(CodecProfileError::UnsupportedCidVersion(0))
+        ));
+
+        // Read from disk without going through `new`
+        let profile: CodecProfile =
+            serde_json::from_str(r#"{"hash": 0, "codec": 297, "cid_version": 1}"#).unwrap();
+        assert!(matches!(
+            profile.hash(),
+            Err(CodecProfileError::UnsupportedHash(0))
+        ));
+        assert!(matches!(
+            profile.codec(),
+            Err(CodecProfileError::UnsupportedCodec(297))
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Golden cids for fixed inputs. These only change if the way data, nodes or
+//!  manifests are encoded or hashed changes, which breaks every existing bucket
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use super::{Block, Cid, DefaultParams, Ipld, IpldCodec, Manifest, MhCode, Node, Object, Version};
+use crate::ipfs_rpc::cid_for_bytes;
+
+// 2023-11-14T22:13:20Z
+const TIMESTAMP: i128 = 1_700_000_000_000_000_000;
+
+fn node_cid(ipld: Ipld) -> Cid {
+    let block =
+        Block::<DefaultParams>::encode(IpldCodec::DagCbor, MhCode::Blake3_256, &ipld).unwrap();
+    *block.cid()
+}
+
+fn map(entries: Vec<(&str, Ipld)>) -> Ipld {
+    Ipld::Map(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<BTreeMap<_, _>>(),
+    )
+}
+
+fn empty_node() -> Node {
+    Node::default()
+}
+
+fn file_node() -> Node {
+    let object = Object::try_from(map(vec![
+        ("created_at", Ipld::Integer(TIMESTAMP)),
+        ("updated_at", Ipld::Integer(TIMESTAMP)),
+        (
+            "metadata",
+            map(vec![("title", Ipld::String("hello".to_string()))]),
+        ),
+    ]))
+    .unwrap();
+    let link = cid_for_bytes(MhCode::Blake3_256, b"hello").unwrap();
+    Node::try_from(map(vec![
+        (".metadata", map(vec![("hello.txt", object.into())])),
+        ("hello.txt", Ipld::Link(link)),
+    ]))
+    .unwrap()
+}
+
+#[test]
+fn raw_blake3() {
+    assert_eq!(
+        cid_for_bytes(MhCode::Blake3_256, b"hello")
+            .unwrap()
+            .to_string(),
+        "bafkr4ihkr4ld3m4gqkjf4reryxsy2s5tkbxprqkow6fin2iiyvreuzzab4"
+    );
+    assert_eq!(
+        cid_for_bytes(MhCode::Blake3_256, b"").unwrap().to_string(),
+        "bafkr4ifpcne3t5pzugtkaqcn5i3nzskjtpfslsnnyejlpte2spfoihzsmi"
+    );
+}
+
+#[test]
+fn raw_sha2() {
+    // The same cid `ipfs add --raw-leaves --cid-version 1` gives
+    assert_eq!(
+        cid_for_bytes(MhCode::Sha2_256, b"hello")
+            .unwrap()
+            .to_string(),
+        "bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq"
+    );
+}
+
+#[test]
+fn empty_node_cid() {
+    assert_eq!(
+        node_cid(empty_node().into()).to_string(),
+        "bafyr4ig4ha5j3odluzvggpwddobf2n3w54svljhwvdx5yrpwpbvi5hj7zq"
+    );
+}
+
+#[test]
+fn dir_node_cid() {
+    let mut node = Node::default();
+    node.put_link("dir", &node_cid(empty_node().into()));
+    assert_eq!(
+        node_cid(node.into()).to_string(),
+        "bafyr4ieiookqw7tu5v52hnm6vz3ulny52taoukrjibua7h46dc66nyvygi"
+    );
+}
+
+#[test]
+fn file_node_cid() {
+    assert_eq!(
+        node_cid(file_node().into()).to_string(),
+        "bafyr4if7c5a5cjjejkwfvqshzgk2mjdv2r77dktzi3fspejwgv5dxfceey"
+    );
+}
+
+#[test]
+fn manifest_cid() {
+    let version = Version::try_from(map(vec![
+        ("build_profile", Ipld::String("release".to_string())),
+        ("build_features", Ipld::String("default".to_string())),
+        ("repo_version", Ipld::String("abc1234".to_string())),
+        ("version", Ipld::String("0.1.0".to_string())),
+    ]))
+    .unwrap();
+    let previous = cid_for_bytes(MhCode::Blake3_256, b"").unwrap();
+    let manifest = Manifest::try_from(map(vec![
+        ("version", version.into()),
+        ("previous", Ipld::Link(previous)),
+        ("data", Ipld::Link(node_cid(file_node().into()))),
+        (
+            "profile",
+            map(vec![
+                ("hash", Ipld::Integer(0x1e)),
+                ("codec", Ipld::Integer(0x71)),
+                ("cid_version", Ipld::Integer(1)),
+            ]),
+        ),
+    ]))
+    .unwrap();
+    // The profile every new bucket gets
+    assert_eq!(manifest.profile(), &super::CodecProfile::default());
+    assert_eq!(
+        node_cid(manifest.into()).to_string(),
+        "bafyr4ifh3fy6mc3wp4n3ybaqickhxdhlgnav27h4j5r56du4gqyd6ydywy"
+    );
+}
@@ -1,8 +1,110 @@
 pub use libipld::cbor::DagCborCodec;
 pub use libipld::cid::multihash::Code as MhCode;
 pub use libipld::cid::Error as CidError;
+pub use libipld::cid::Version as CidVersion;
 pub use libipld::store::DefaultParams;
 pub use libipld::Block;
 pub use libipld::Cid;
 pub use libipld::Ipld;
 pub use libipld::IpldCodec;
+
+use std::str::FromStr;
+
+/// Checks for what a cid addresses within a bucket
+pub trait LeakyCid {
+    /// Whether the cid addresses a node (or manifest) block, which are always dag-cbor
+    fn is_node_cid(&self) -> bool;
+    /// Whether the cid addresses file data. Small files are raw blocks,
+    ///  chunked and imported ones are dag-pb
+    fn is_data_cid(&self) -> bool;
+}
+
+impl LeakyCid for Cid {
+    fn is_node_cid(&self) -> bool {
+        matches!(IpldCodec::try_from(self.codec()), Ok(IpldCodec::DagCbor))
+    }
+
+    fn is_data_cid(&self) -> bool {
+        matches!(
+            IpldCodec::try_from(self.codec()),
+            Ok(IpldCodec::Raw) | Ok(IpldCodec::DagPb)
+        )
+    }
+}
+
+/// Parse a user provided cid, rejecting anything a bucket can't contain
+pub fn parse_leaky_cid(s: &str) -> Result<Cid, LeakyCidError> {
+    let cid = Cid::from_str(s.trim())?;
+    if cid.version() != CidVersion::V1 {
+        return Err(LeakyCidError::UnsupportedVersion(cid));
+    }
+    if !cid.is_node_cid() && !cid.is_data_cid() {
+        return Err(LeakyCidError::UnsupportedCodec(cid, cid.codec()));
+    }
+    Ok(cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LeakyCidError {
+    #[error("invalid cid: {0}")]
+    Invalid(#[from] CidError),
+    #[error("unsupported cid version, only v1 cids are used: {0}")]
+    UnsupportedVersion(Cid),
+    #[error("cid {0} has unsupported codec 0x{1:x}")]
+    UnsupportedCodec(Cid, u64),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libipld::multihash::MultihashDigest;
+
+    fn cid(codec: IpldCodec) -> Cid {
+        Cid::new_v1(codec.into(), MhCode::Blake3_256.digest(b"hello"))
+    }
+
+    #[test]
+    fn parse_supported_cids() {
+        let node = cid(IpldCodec::DagCbor);
+        let parsed = parse_leaky_cid(&node.to_string()).unwrap();
+        assert_eq!(parsed, node);
+        assert!(parsed.is_node_cid());
+        assert!(!parsed.is_data_cid());
+
+        let data = cid(IpldCodec::Raw);
+        let parsed = parse_leaky_cid(&data.to_string()).unwrap();
+        assert_eq!(parsed, data);
+        assert!(parsed.is_data_cid());
+        assert!(!parsed.is_node_cid());
+    }
+
+    #[test]
+    fn parse_rejects_v0() {
+        let v0 = Cid::new_v0(MhCode::Sha2_256.digest(b"hello")).unwrap();
+        assert!(matches!(
+            parse_leaky_cid(&v0.to_string()),
+            Err(LeakyCidError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_codec() {
+        // dag-jose isn't something a bucket ever links to
+        let other = Cid::new_v1(0x85, MhCode::Blake3_256.digest(b"hello"));
+        assert!(matches!(
+            parse_leaky_cid(&other.to_string()),
+            Err(LeakyCidError::UnsupportedCodec(_, 0x85))
+        ));
+        // Nodes are only ever dag-cbor, so a dag-json cid points at nothing in a bucket
+        let dag_json = cid(IpldCodec::DagJson);
+        assert!(!dag_json.is_node_cid());
+        assert!(matches!(
+            parse_leaky_cid(&dag_json.to_string()),
+            Err(LeakyCidError::UnsupportedCodec(_, 0x0129))
+        ));
+        assert!(matches!(
+            parse_leaky_cid("not a cid"),
+            Err(LeakyCidError::Invalid(_))
+        ));
+    }
+}
@@ -2,8 +2,9 @@ use std::convert::TryFrom;
 
 use serde::{Deserialize, Serialize};
 
+use super::codec_profile::CodecProfile;
 use super::version::Version;
-use super::{Cid, Ipld};
+use super::{Cid, Ipld, LeakyCid};
 
 /// Manifest
 #[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -14,6 +15,9 @@ pub struct Manifest {
     previous: Cid,
     /// data node CID
     data: Cid,
+    /// How blocks in the bucket are encoded and addressed
+    #[serde(default)]
+    profile: CodecProfile,
 }
 
 impl Into<Ipld> for Manifest {
@@ -22,6 +26,7 @@ impl Into<Ipld> for Manifest {
         map.insert("version".to_string(), self.version.clone().into());
         map.insert("previous".to_string(), Ipld::Link(self.previous().clone()));
         map.insert("data".to_string(), Ipld::Link(self.data.clone()));
+        map.insert("profile".to_string(), self.profile.into());
         Ipld::Map(map)
     }
 }
@@ -43,11 +48,17 @@ impl TryFrom<Ipld> for Manifest {
                     Some(Ipld::Link(cid)) => *cid,
                     _ => return Err(ManifestError::MissingField("data link".to_string())),
                 };
+                // Manifests from before profiles were recorded use the default
+                let profile = match map.get("profile") {
+                    Some(ipld) => CodecProfile::try_from(ipld.clone())?,
+                    None => CodecProfile::default(),
+                };
 
                 Ok(Manifest {
                     version,
                     previous,
                     data,
+                    profile,
                 })
             }
             _ => Err(ManifestError::MissingField("map".to_string())),
@@ -68,19 +79,82 @@ impl Manifest {
         &self.data
     }
 
+    pub fn profile(&self) -> &CodecProfile {
+        &self.profile
+    }
+
     pub fn set_data(&mut self, cid: Cid) {
         self.data = cid;
     }
 
+    /// Set the data node, checking that the cid is encoded with the profile's codec.
+    ///  Anything else can't be a node, and would leave the bucket unreadable
+    pub fn try_set_data(&mut self, cid: Cid) -> Result<(), ManifestError> {
+        if !self.is_node_cid(&cid) {
+            return Err(ManifestError::DataNotNode(cid));
+        }
+        self.data = cid;
+        Ok(())
+    }
+
+    /// Whether a cid is encoded with the profile's codec, and so could point to a node
+    pub fn is_node_cid(&self, cid: &Cid) -> bool {
+        cid.is_node_cid()
+            && self
+                .profile
+                .codec()
+                .map_or(false, |codec| cid.codec() == u64::from(codec))
+    }
+
     pub fn set_previous(&mut self, cid: Cid) {
         self.previous = cid;
     }
+
+    pub fn set_profile(&mut self, profile: CodecProfile) {
+        self.profile = profile;
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum ManifestError {
     #[error("version error")]
     VersionError(#[from] super::version::VersionError),
+    #[error("codec profile error: {0}")]
+    CodecProfile(#[from] super::codec_profile::CodecProfileError),
     #[error("missing field: {0}")]
     MissingField(String),
+    #[error("data cid does not point to a node: {0}")]
+    DataNotNode(Cid),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{Block, DefaultParams, IpldCodec, MhCode};
+
+    #[test]
+    fn try_set_data() {
+        let mut manifest = Manifest::default();
+        let node = Block::<DefaultParams>::encode(
+            IpldCodec::DagCbor,
+            MhCode::Blake3_256,
+            &Ipld::Map(Default::default()),
+        )
+        .unwrap();
+        manifest.try_set_data(*node.cid()).unwrap();
+        assert_eq!(manifest.data(), node.cid());
+
+        let raw = Block::<DefaultParams>::encode(
+            IpldCodec::Raw,
+            MhCode::Blake3_256,
+            &Ipld::Bytes(b"foo".to_vec()),
+        )
+        .unwrap();
+        match manifest.try_set_data(*raw.cid()) {
+            Err(ManifestError::DataNotNode(cid)) => assert_eq!(&cid, raw.cid()),
+            other => panic!("expected DataNotNode, got {:?}", other),
+        }
+        // Left as it was
+        assert_eq!(manifest.data(), node.cid());
+    }
 }
@@ -1,11 +1,18 @@
+mod codec_profile;
+#[cfg(test)]
+mod golden;
 mod ipld;
 mod manifest;
 mod node;
 mod object;
 mod version;
 
-pub use ipld::{Block, Cid, CidError, DagCborCodec, DefaultParams, Ipld, IpldCodec, MhCode};
-pub use manifest::Manifest;
-pub use node::Node;
+pub use codec_profile::{CodecProfile, CodecProfileError};
+pub use ipld::{
+    parse_leaky_cid, Block, Cid, CidError, CidVersion, DagCborCodec, DefaultParams, Ipld,
+    IpldCodec, LeakyCid, LeakyCidError, MhCode,
+};
+pub use manifest::{Manifest, ManifestError};
+pub use node::{Node, RESERVED_NAMES};
 pub use object::Object;
 pub use version::Version;
@@ -8,6 +8,12 @@ use super::object::Object;
 // Reserved metadata key for detailing what links
 //  within have visible metatdata attached to them
 const METADATA_KEY: &str = ".metadata";
+// Reserved key for the metadata stamped onto new links
+//  added to the node without any of their own
+const DEFAULT_KEY: &str = ".default";
+
+/// Names that can't be used for links within a node
+pub const RESERVED_NAMES: &[&str] = &[METADATA_KEY, DEFAULT_KEY];
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Node(BTreeMap<String, Ipld>);
@@ -48,12 +54,12 @@ impl Deref for Node {
 impl Node {
     // Write a link to the node. Use this for creating 'directories'
     pub fn put_link(&mut self, name: &str, link: &Cid) {
-        assert_ne!(name, METADATA_KEY);
+        assert!(!RESERVED_NAMES.contains(&name));
         self.0.insert(name.to_string(), Ipld::Link(*link));
     }
 
     pub fn put_object(&mut self, name: &str, maybe_metadata: Option<&BTreeMap<String, Ipld>>) {
-        assert_ne!(name, METADATA_KEY);
+        assert!(!RESERVED_NAMES.contains(&name));
         let metadata_ipld = self.0.get(METADATA_KEY).unwrap().clone();
         let mut metadata_map = match metadata_ipld {
             Ipld::Map(metadata) => metadata,
@@ -79,7 +85,7 @@ impl Node {
         maybe_link: Option<&Cid>,
         maybe_metadata: Option<&BTreeMap<String, Ipld>>,
     ) {
-        assert_ne!(name, METADATA_KEY);
+        assert!(!RESERVED_NAMES.contains(&name));
 
         if let Some(link) = maybe_link {
             self.put_link(name, link);
@@ -116,7 +122,7 @@ impl Node {
 
     // Just get the link from the node, without any metadata
     pub fn get_link(&self, name: &str) -> Option<Cid> {
-        assert_ne!(name, METADATA_KEY);
+        assert!(!RESERVED_NAMES.contains(&name));
         self.0.get(name).and_then(|ipld| match ipld {
             Ipld::Link(cid) => Some(*cid),
             _ => None,
@@ -137,8 +143,31 @@ impl Node {
     }
 
     pub fn size(&self) -> usize {
-        // Get the length of the node, minus the metadata key
-        self.0.len() - 1
+        // Get the length of the node, minus any reserved keys
+        self.0
+            .keys()
+            .filter(|k| !RESERVED_NAMES.contains(&k.as_str()))
+            .count()
+    }
+
+    // Set (or clear) the metadata stamped onto new links that don't have any
+    pub fn set_default(&mut self, maybe_metadata: Option<&BTreeMap<String, Ipld>>) {
+        match maybe_metadata {
+            Some(metadata) => {
+                self.0
+                    .insert(DEFAULT_KEY.to_string(), Ipld::Map(metadata.clone()));
+            }
+            None => {
+                self.0.remove(DEFAULT_KEY);
+            }
+        }
+    }
+
+    pub fn get_default(&self) -> Option<BTreeMap<String, Ipld>> {
+        match self.0.get(DEFAULT_KEY) {
+            Some(Ipld::Map(metadata)) => Some(metadata.clone()),
+            _ => None,
+        }
     }
 
     // Get the fully constructed object from the node, if it exists
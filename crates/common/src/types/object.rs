@@ -1,9 +1,13 @@
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::str::FromStr;
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Number, Value};
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
-use super::Ipld;
+use super::{Cid, Ipld};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Object {
@@ -91,7 +95,124 @@ impl TryFrom<Ipld> for Object {
     }
 }
 
+/// Objects are represented in JSON with RFC3339 timestamps.
+///  Links within metadata are written as `{"/": "<cid>"}` like dag-json
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Object::from_json(value).map_err(serde::de::Error::custom)
+    }
+}
+
+fn ipld_to_json(ipld: &Ipld) -> Result<Value, ObjectIpldError> {
+    Ok(match ipld {
+        Ipld::Null => Value::Null,
+        Ipld::Bool(b) => Value::Bool(*b),
+        Ipld::Integer(i) => {
+            let i = i64::try_from(*i).map_err(|_| ObjectIpldError::UnsupportedJson)?;
+            Value::Number(Number::from(i))
+        }
+        Ipld::Float(f) => {
+            Value::Number(Number::from_f64(*f).ok_or(ObjectIpldError::UnsupportedJson)?)
+        }
+        Ipld::String(s) => Value::String(s.clone()),
+        Ipld::List(l) => Value::Array(l.iter().map(ipld_to_json).collect::<Result<_, _>>()?),
+        Ipld::Map(m) => {
+            let mut map = Map::new();
+            for (k, v) in m.iter() {
+                map.insert(k.clone(), ipld_to_json(v)?);
+            }
+            Value::Object(map)
+        }
+        Ipld::Link(cid) => {
+            let mut map = Map::new();
+            map.insert("/".to_string(), Value::String(cid.to_string()));
+            Value::Object(map)
+        }
+        Ipld::Bytes(_) => return Err(ObjectIpldError::UnsupportedJson),
+    })
+}
+
+fn json_to_ipld(value: &Value) -> Result<Ipld, ObjectIpldError> {
+    Ok(match value {
+        Value::Null => Ipld::Null,
+        Value::Bool(b) => Ipld::Bool(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Ipld::Integer(i as i128),
+            None => Ipld::Float(n.as_f64().ok_or(ObjectIpldError::UnsupportedJson)?),
+        },
+        Value::String(s) => Ipld::String(s.clone()),
+        Value::Array(l) => Ipld::List(l.iter().map(json_to_ipld).collect::<Result<_, _>>()?),
+        Value::Object(m) => match (m.len(), m.get("/")) {
+            (1, Some(Value::String(cid))) => {
+                Ipld::Link(Cid::from_str(cid).map_err(|_| ObjectIpldError::UnsupportedJson)?)
+            }
+            _ => {
+                let mut map = BTreeMap::new();
+                for (k, v) in m.iter() {
+                    map.insert(k.clone(), json_to_ipld(v)?);
+                }
+                Ipld::Map(map)
+            }
+        },
+    })
+}
+
 impl Object {
+    /// Render the object in its canonical JSON form
+    pub fn to_json(&self) -> Result<Value, ObjectIpldError> {
+        let mut map = Map::new();
+        map.insert(
+            OBJECT_CREATED_AT_LABEL.to_string(),
+            Value::String(self.created_at.format(&Rfc3339)?),
+        );
+        map.insert(
+            OBJECT_UPDATED_AT_LABEL.to_string(),
+            Value::String(self.updated_at.format(&Rfc3339)?),
+        );
+        map.insert(
+            OBJECT_METADATA_LABEL.to_string(),
+            ipld_to_json(&Ipld::Map(self.metadata.clone()))?,
+        );
+        Ok(Value::Object(map))
+    }
+
+    /// Read an object from its canonical JSON form
+    pub fn from_json(value: Value) -> Result<Self, ObjectIpldError> {
+        let map = match value {
+            Value::Object(map) => map,
+            _ => return Err(ObjectIpldError::NotMap),
+        };
+        let timestamp = |label: &str| match map.get(label) {
+            Some(Value::String(s)) => Ok(OffsetDateTime::parse(s, &Rfc3339)?),
+            _ => Err(ObjectIpldError::MissingMapMember(label.to_string())),
+        };
+        let created_at = timestamp(OBJECT_CREATED_AT_LABEL)?;
+        let updated_at = timestamp(OBJECT_UPDATED_AT_LABEL)?;
+        let metadata = match map.get(OBJECT_METADATA_LABEL).map(json_to_ipld) {
+            Some(Ok(Ipld::Map(metadata))) => metadata,
+            Some(Err(e)) => return Err(e),
+            _ => {
+                return Err(ObjectIpldError::MissingMapMember(
+                    OBJECT_METADATA_LABEL.to_string(),
+                ))
+            }
+        };
+        Ok(Self {
+            created_at,
+            updated_at,
+            metadata,
+        })
+    }
+
     pub fn new(maybe_metadata: Option<&BTreeMap<String, Ipld>>) -> Self {
         let metadata = match maybe_metadata {
             Some(metadata) => metadata.clone(),
@@ -142,4 +263,58 @@ pub enum ObjectIpldError {
     SerdeJson(#[from] serde_json::Error),
     #[error("ipld data is not map")]
     NotMap,
+    #[error("could not format datetime: {0}")]
+    FormatDateTime(#[from] time::error::Format),
+    #[error("could not parse datetime: {0}")]
+    ParseDateTime(#[from] time::error::Parse),
+    #[error("value can't be represented in json")]
+    UnsupportedJson,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn object() -> Object {
+        use libipld::multihash::MultihashDigest;
+        let link = Cid::new_v1(0x55, crate::types::MhCode::Blake3_256.digest(b"hello"));
+        let mut metadata = BTreeMap::new();
+        metadata.insert("title".to_string(), Ipld::String("hello".to_string()));
+        metadata.insert("count".to_string(), Ipld::Integer(3));
+        metadata.insert("ratio".to_string(), Ipld::Float(0.5));
+        metadata.insert("draft".to_string(), Ipld::Bool(false));
+        metadata.insert("link".to_string(), Ipld::Link(link));
+        Object::new(Some(&metadata))
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let object = object();
+        let json = serde_json::to_string(&object).unwrap();
+        let parsed: Object = serde_json::from_str(&json).unwrap();
+        assert_eq!(object, parsed);
+    }
+
+    #[test]
+    fn json_timestamps_are_rfc3339() {
+        let object = object();
+        let json = object.to_json().unwrap();
+        let created_at = json.get(OBJECT_CREATED_AT_LABEL).unwrap().as_str().unwrap();
+        assert_eq!(
+            OffsetDateTime::parse(created_at, &Rfc3339).unwrap(),
+            *object.created_at()
+        );
+    }
+
+    #[test]
+    fn json_ipld_round_trip() {
+        let object = object();
+        // Ipld -> Object -> json -> Object -> Ipld should leave the timestamps alone
+        let ipld: Ipld = object.clone().into();
+        let from_ipld = Object::try_from(ipld.clone()).unwrap();
+        let json = serde_json::to_string(&from_ipld).unwrap();
+        let from_json: Object = serde_json::from_str(&json).unwrap();
+        let back: Ipld = from_json.into();
+        assert_eq!(ipld, back);
+    }
 }